@@ -1,6 +1,25 @@
 //! Operations on the EE caches.
 
+use prussia_rt::cache;
+
+/// FlushCache (and iFlushCache): write back or invalidate a whole cache, depending on `mode`.
+///
+/// - 0: write back and invalidate the data cache.
+/// - 1: invalidate the data cache.
+/// - 2: invalidate the instruction cache.
+/// - 3: invalidate both caches.
+///
+/// Other modes do nothing.
 #[no_mangle]
-pub extern "C" fn flush(_mode: u32) {
-    // unimplemented
+pub extern "C" fn flush(mode: u32) {
+    match mode {
+        0 => cache::writeback_invalidate_data_all(),
+        1 => cache::invalidate_data_all(),
+        2 => cache::invalidate_instruction_all(),
+        3 => {
+            cache::invalidate_instruction_all();
+            cache::invalidate_data_all();
+        }
+        _ => {}
+    }
 }
@@ -29,6 +29,7 @@ pub fn init() {
         SYSCALL_HANDLERS[0x3C] = thread::init_main_thread as usize;
         SYSCALL_HANDLERS[0x3D] = thread::init_heap as usize;
         SYSCALL_HANDLERS[0x64] = cache::flush as usize;
+        SYSCALL_HANDLERS[0x68] = cache::flush as usize;
         SYSCALL_HANDLERS[0x70] = gs::imr::imr as usize;
         SYSCALL_HANDLERS[0x71] = gs::imr::put_imr as usize;
     }
@@ -5,6 +5,7 @@
 #![feature(asm_experimental_arch)]
 
 use core::arch::asm;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
 
 /// EE Component Reset Flags, passed to `reset_ee`.
 pub enum EEResetFlag {
@@ -38,10 +39,16 @@ pub fn reset_ee(reset_flag: u32) {
 /// The Graphics Synthesizer video mode, passed to `set_gs_crt`.
 #[repr(i16)]
 pub enum VideoMode {
-    /// PAL, 720x576@50Hz interlaced, as used in most of Europe.
-    NTSC = 2,
     /// NTSC, 720x480@59.97Hz interlaced, as used in North America and Japan.
+    NTSC = 2,
+    /// PAL, 720x576@50Hz interlaced, as used in most of Europe.
     PAL = 3,
+    /// 720x480@59.94Hz progressive, over component video.
+    DTV480P = 0x50,
+    /// 1920x1080@59.94Hz interlaced, over component video.
+    DTV1080I = 0x51,
+    /// 1280x720@59.94Hz progressive, over component video.
+    DTV720P = 0x52,
 }
 
 /// Whether interlacing is enabled, passed to `set_gs_crt`.
@@ -56,10 +63,10 @@ pub enum Interlacing {
 /// Whether to read every line or every other line, passed to `set_gs_crt`.
 #[repr(i16)]
 pub enum FieldFrameMode {
-    /// Read every line from a frame.
-    Frame = 0,
     /// Read every other line from a frame.
-    Field = 1,
+    Field = 0,
+    /// Read every line from a frame.
+    Frame = 1,
 }
 
 /// Configure the Graphics Synthesizer's display controller to output a given VideoMode.
@@ -216,7 +223,13 @@ pub enum DMACause {
     /// An interrupt from the Scratch Pad Ram FROM channel.
     FROMSPR,
     /// An interrupt from the Scratch Pad Ram TO channel.
-    TOSPR
+    TOSPR,
+    /// A DMA transfer has stalled under stall control.
+    SIS = 13,
+    /// The DMA memory FIFO is empty.
+    MEIS,
+    /// The DMA controller has encountered a bus error.
+    BEIS,
 }
 
 /// Add a DMAC handler
@@ -322,3 +335,155 @@ pub fn sleep_thread() {
         );
     }
 }
+
+/// Returns the ID of the current thread.
+pub fn get_thread_id() -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "syscall",
+            out("$2") result, // v0
+            in("$3") 0x2F, // v1
+        );
+    }
+    result
+}
+
+/// Wakes the given thread if it is sleeping, otherwise increments its 'wakeup_count'.
+/// Returns the thread ID if the operation succeeds, -1 if it fails
+pub fn wakeup_thread(thread_id: i32) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "syscall",
+            out("$2") result, // v0
+            in("$3") 0x33, // v1
+            in("$4") thread_id, // a0
+        );
+    }
+    result
+}
+
+/// The interrupt handler version of `wakeup_thread`.
+/// Returns the thread ID if the operation succeeds, -1 if it fails
+pub fn iwakeup_thread(thread_id: i32) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "syscall",
+            out("$2") result, // v0
+            in("$3") -0x34, // v1
+            in("$4") thread_id, // a0
+        );
+    }
+    result
+}
+
+/// No thread is waiting in a slot of an `Event`.
+const NO_THREAD: i32 = -1;
+
+/// An event signalled by an interrupt handler, which up to `N` threads at a time can sleep until.
+///
+/// The handler calls `isignal`, which counts the event and wakes the threads waiting for it. A
+/// thread checks whether it still has to wait and registers itself with interrupts disabled, so
+/// the event cannot be signalled in between and leave a wakeup pending. A wakeup from elsewhere
+/// while the thread sleeps can still leave one from the event pending.
+pub struct Event<const N: usize> {
+    count: AtomicU32,
+    waiting: [AtomicI32; N],
+}
+
+impl<const N: usize> Event<N> {
+    /// An event which has not been signalled, with no threads waiting.
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NOT_WAITING: AtomicI32 = AtomicI32::new(NO_THREAD);
+        Event {
+            count: AtomicU32::new(0),
+            waiting: [NOT_WAITING; N],
+        }
+    }
+
+    /// The number of times the event has been signalled.
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Count the event and wake the threads waiting for it. Call this from the interrupt handler.
+    pub fn isignal(&self) {
+        // The R5900 has no atomic read-modify-write instructions, but interrupts are disabled in
+        // handlers, so nothing can run between a load and a store.
+        self.count.store(
+            self.count.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Release,
+        );
+        for slot in &self.waiting {
+            let thread = slot.load(Ordering::Acquire);
+            if thread != NO_THREAD {
+                slot.store(NO_THREAD, Ordering::Release);
+                iwakeup_thread(thread);
+            }
+        }
+    }
+
+    /// Put the current thread to sleep until `done` returns true, checking it again each time the
+    /// event is signalled. `done` is called with interrupts disabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` other threads are already waiting.
+    pub fn sleep_until<F: FnMut() -> bool>(&self, mut done: F) {
+        let thread = get_thread_id();
+        loop {
+            let mut finished = false;
+            let mut full = false;
+            prussia_rt::interrupts::free(|| {
+                let registered = self
+                    .waiting
+                    .iter()
+                    .find(|slot| slot.load(Ordering::Acquire) == thread);
+                finished = done();
+                if finished {
+                    // Woken by something else, so the handler has not taken the slot.
+                    if let Some(slot) = registered {
+                        slot.store(NO_THREAD, Ordering::Release);
+                    }
+                } else if registered.is_none() {
+                    match self
+                        .waiting
+                        .iter()
+                        .find(|slot| slot.load(Ordering::Acquire) == NO_THREAD)
+                    {
+                        Some(slot) => slot.store(thread, Ordering::Release),
+                        None => full = true,
+                    }
+                }
+            });
+            assert!(!full, "More than {} threads are waiting for an event", N);
+            if finished {
+                return;
+            }
+            sleep_thread();
+        }
+    }
+}
+
+/// The cache operation performed by `flush_cache`.
+pub enum CacheMode {
+    /// Write back dirty data cache lines to memory, and invalidate the data cache.
+    WritebackData = 0,
+    /// Invalidate the instruction cache.
+    InvalidateInstruction = 2,
+}
+
+/// Write back or invalidate the EE caches.
+/// Memory written by the CPU must be written back before a DMA transfer reads it.
+pub fn flush_cache(mode: CacheMode) {
+    unsafe {
+        asm!(
+            "syscall",
+            in("$3") 0x64, // v1
+            in("$4") mode as i32, // a0
+        );
+    }
+}
@@ -0,0 +1,540 @@
+//! DMA Source Chain mode.
+//!
+//! In Source Chain mode, the DMAC reads a DMAtag from the tag address (TADR), transfers the data
+//! it describes, and then follows it to the next tag. The tag ID decides both where the data is
+//! and where the next tag is:
+//!
+//! | ID   | Data                  | Next tag                          |
+//! |------|-----------------------|-----------------------------------|
+//! | REFE | ADDR                  | none; the transfer ends           |
+//! | CNT  | after the tag         | after the data                    |
+//! | NEXT | after the tag         | ADDR                              |
+//! | REF  | ADDR                  | after the tag                     |
+//! | REFS | ADDR, stall-controlled | after the tag                    |
+//! | CALL | after the tag         | ADDR, pushing the return address  |
+//! | RET  | after the tag         | the popped return address         |
+//! | END  | after the tag         | none; the transfer ends           |
+//!
+//! The VIF0, VIF1 and GIF channels have two address stack registers (ASR0/ASR1), so CALLs nest at
+//! most two deep. `ChainBuilder` lays out tags and their data, and checks that every chain is
+//! terminated and that subroutines are not nested too deeply.
+//!
+//! # Examples
+//!
+//! ```
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::chain::ChainBuilder;
+//!
+//! fn send_twice(gif: prussia_dma::Gif) -> prussia_dma::Gif {
+//!     static mut PAYLOAD: Aligned<A16, [u128; 2]> = Aligned([0; 2]);
+//!     static mut BUFFER: Aligned<A16, [u128; 8]> = Aligned([0; 8]);
+//!
+//!     let payload = unsafe { &PAYLOAD };
+//!     let mut chain = ChainBuilder::new(unsafe { &mut BUFFER });
+//!     chain.reference(payload).reference(payload).end(&[]);
+//!
+//!     let (gif, _) = prussia_dma::Transfer::chain(gif, chain.finish(), false).wait();
+//!     gif
+//! }
+//! ```
+
+use core::{mem, ptr};
+
+use aligned::{Aligned, A16};
+
+use crate::physical_address;
+
+/// The most quadwords a single DMAtag can transfer.
+pub const MAX_QWC: usize = 0xFFFF;
+
+/// How deeply CALL tags can nest, limited by the ASR0/ASR1 address stack.
+pub const MAX_CALL_DEPTH: u8 = 2;
+
+/// DMAtag IDs for Source Chain mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagId {
+    /// Transfer QWC quadwords from ADDR, then end.
+    Refe = 0,
+    /// Transfer the QWC quadwords after the tag, then read the tag after them.
+    Cnt = 1,
+    /// Transfer the QWC quadwords after the tag, then read the tag at ADDR.
+    Next = 2,
+    /// Transfer QWC quadwords from ADDR, then read the tag after this one.
+    Ref = 3,
+    /// Like `Ref`, but stalling on the stall address.
+    Refs = 4,
+    /// Transfer the QWC quadwords after the tag, push the address after them, then read the tag
+    /// at ADDR.
+    Call = 5,
+    /// Transfer the QWC quadwords after the tag, then read the tag at the popped address.
+    Ret = 6,
+    /// Transfer the QWC quadwords after the tag, then end.
+    End = 7,
+}
+
+impl TagId {
+    fn from_bits(bits: u64) -> Self {
+        match bits & 7 {
+            0 => TagId::Refe,
+            1 => TagId::Cnt,
+            2 => TagId::Next,
+            3 => TagId::Ref,
+            4 => TagId::Refs,
+            5 => TagId::Call,
+            6 => TagId::Ret,
+            _ => TagId::End,
+        }
+    }
+}
+
+/// A DMAtag: the lower doubleword of a Source Chain mode tag quadword.
+///
+/// | Bits  | Field                                       |
+/// |-------|---------------------------------------------|
+/// | 0-15  | QWC, the quadword count                     |
+/// | 26-27 | PCE, priority control                       |
+/// | 28-30 | ID                                          |
+/// | 31    | IRQ, interrupt when the tag is read         |
+/// | 32-62 | ADDR, a quadword-aligned address            |
+/// | 63    | SPR, whether ADDR is in scratchpad memory   |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaTag(u64);
+
+impl DmaTag {
+    /// Create a tag with the given ID, quadword count and address. Bit 31 of `addr` selects
+    /// scratchpad RAM, as in the addresses from `physical_address`, and becomes the SPR bit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `qwc` exceeds `MAX_QWC` or `addr` is not quadword-aligned.
+    pub fn new(id: TagId, qwc: usize, addr: u32) -> Self {
+        assert!(qwc <= MAX_QWC, "DMAtag QWC {} is too large", qwc);
+        assert!(addr & 0xF == 0, "DMAtag address {:#x} is not aligned", addr);
+        DmaTag(qwc as u64 | (id as u64) << 28 | ((addr & 0x7FFF_FFFF) as u64) << 32)
+            .with_spr(addr >> 31 != 0)
+    }
+
+    /// A CNT tag, for `qwc` quadwords following it.
+    pub fn cnt(qwc: usize) -> Self {
+        DmaTag::new(TagId::Cnt, qwc, 0)
+    }
+
+    /// A NEXT tag, for `qwc` quadwords following it and then the tag at `addr`.
+    pub fn next(qwc: usize, addr: u32) -> Self {
+        DmaTag::new(TagId::Next, qwc, addr)
+    }
+
+    /// A REF tag, for `qwc` quadwords at `addr`.
+    pub fn reference(qwc: usize, addr: u32) -> Self {
+        DmaTag::new(TagId::Ref, qwc, addr)
+    }
+
+    /// A REFS tag, for `qwc` quadwords at `addr` under stall control.
+    pub fn refs(qwc: usize, addr: u32) -> Self {
+        DmaTag::new(TagId::Refs, qwc, addr)
+    }
+
+    /// A REFE tag, for `qwc` quadwords at `addr` and then the end of the transfer.
+    pub fn refe(qwc: usize, addr: u32) -> Self {
+        DmaTag::new(TagId::Refe, qwc, addr)
+    }
+
+    /// A CALL tag, for `qwc` quadwords following it and then the subroutine at `addr`.
+    pub fn call(qwc: usize, addr: u32) -> Self {
+        DmaTag::new(TagId::Call, qwc, addr)
+    }
+
+    /// A RET tag, for `qwc` quadwords following it and then a return from a subroutine.
+    pub fn ret(qwc: usize) -> Self {
+        DmaTag::new(TagId::Ret, qwc, 0)
+    }
+
+    /// An END tag, for `qwc` quadwords following it and then the end of the transfer.
+    pub fn end(qwc: usize) -> Self {
+        DmaTag::new(TagId::End, qwc, 0)
+    }
+
+    /// Raise an interrupt once this tag's data is transferred, if CHCR.TIE is set.
+    pub fn with_irq(self, irq: bool) -> Self {
+        DmaTag(self.0 & !(1 << 31) | (irq as u64) << 31)
+    }
+
+    /// Mark ADDR as a scratchpad memory address.
+    pub fn with_spr(self, spr: bool) -> Self {
+        DmaTag(self.0 & !(1 << 63) | (spr as u64) << 63)
+    }
+
+    /// Set the priority control field.
+    pub fn with_pce(self, pce: u8) -> Self {
+        DmaTag(self.0 & !(3 << 26) | ((pce & 3) as u64) << 26)
+    }
+
+    /// The tag ID.
+    pub fn id(&self) -> TagId {
+        TagId::from_bits(self.0 >> 28)
+    }
+
+    /// The quadword count.
+    pub fn qwc(&self) -> usize {
+        (self.0 & 0xFFFF) as usize
+    }
+
+    /// The address, with bit 31 set if it is in scratchpad RAM.
+    pub fn addr(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Whether the tag raises an interrupt.
+    pub fn irq(&self) -> bool {
+        self.0 & (1 << 31) != 0
+    }
+
+    /// The tag as a quadword, with `upper` in the upper doubleword. With CHCR.TTE set, the VIF
+    /// channels receive the upper doubleword as two VIFcodes.
+    pub fn to_qword(self, upper: u64) -> u128 {
+        self.0 as u128 | (upper as u128) << 64
+    }
+}
+
+impl From<DmaTag> for u64 {
+    fn from(tag: DmaTag) -> Self {
+        tag.0
+    }
+}
+
+impl From<u64> for DmaTag {
+    fn from(bits: u64) -> Self {
+        DmaTag(bits)
+    }
+}
+
+/// The DMA address of `data`.
+fn address<T>(data: &Aligned<A16, [T]>) -> u32 {
+    physical_address(data.as_ptr() as usize)
+}
+
+/// Write back the data cache lines covering `data`, so that DMA reads what the CPU wrote.
+pub(crate) fn writeback<T>(data: &[T]) {
+    #[cfg(target_arch = "mips")]
+    prussia_rt::cache::writeback_data(data.as_ptr() as usize, mem::size_of_val(data));
+    #[cfg(not(target_arch = "mips"))]
+    let _ = data;
+}
+
+/// The number of whole quadwords in `data`.
+fn qwords<T>(data: &Aligned<A16, [T]>) -> usize {
+    (data.len() * mem::size_of::<T>()) / 16
+}
+
+/// A finished chain of DMAtags, ready to be transferred or called.
+pub struct Chain<'a> {
+    data: &'a mut Aligned<A16, [u128]>,
+    depth: u8,
+}
+
+impl<'a> Chain<'a> {
+    /// The DMA address of the first tag.
+    pub fn address(&self) -> u32 {
+        address(self.data)
+    }
+
+    /// How many CALLs deep this chain runs: 0 for a main chain, and 1 or more for subroutines.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// The tags and data of the chain.
+    pub fn as_slice(&self) -> &[u128] {
+        self.data
+    }
+
+    /// Give back the buffer holding the chain.
+    pub fn into_inner(self) -> &'a mut Aligned<A16, [u128]> {
+        self.data
+    }
+}
+
+/// Lays out a chain of DMAtags and their data in a 16-byte aligned buffer.
+///
+/// Data that lives elsewhere is referenced with REF-style tags, so its lifetime is tied to the
+/// buffer's. The chain borrows referenced data and called subroutines immutably, so they are
+/// written back from the data cache as they are added, and `Transfer::chain` only writes back the
+/// chain itself. Passing a buffer reborrowed with `prussia_rt::uncached::uncached` builds the chain
+/// straight into memory.
+pub struct ChainBuilder<'a> {
+    buffer: &'a mut Aligned<A16, [u128]>,
+    len: usize,
+    depth: u8,
+    terminated: bool,
+}
+
+impl<'a> ChainBuilder<'a> {
+    /// Start a main chain in `buffer`, which must end with `end` or `refe`.
+    pub fn new(buffer: &'a mut Aligned<A16, [u128]>) -> Self {
+        ChainBuilder {
+            buffer,
+            len: 0,
+            depth: 0,
+            terminated: false,
+        }
+    }
+
+    /// Start a subroutine in `buffer`, which must end with `ret`. It can be called from chains one
+    /// level less deep.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is zero or more than `MAX_CALL_DEPTH`.
+    pub fn subroutine(buffer: &'a mut Aligned<A16, [u128]>, depth: u8) -> Self {
+        assert!(
+            (1..=MAX_CALL_DEPTH).contains(&depth),
+            "Subroutine depth must be between 1 and {}",
+            MAX_CALL_DEPTH
+        );
+        ChainBuilder {
+            depth,
+            ..ChainBuilder::new(buffer)
+        }
+    }
+
+    /// The number of quadwords written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append a tag, with `upper` in the upper doubleword of its quadword.
+    ///
+    /// This does not check the chain's structure, or write back data the tag refers to; prefer the
+    /// tag-specific methods.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is full or the chain has already ended.
+    pub fn tag(&mut self, tag: DmaTag, upper: u64) -> &mut Self {
+        self.push(&[tag.to_qword(upper)])
+    }
+
+    /// Append raw quadwords, such as the data following a tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is full or the chain has already ended.
+    pub fn push(&mut self, data: &[u128]) -> &mut Self {
+        assert!(!self.terminated, "The DMA chain has already ended");
+        let buffer: &mut [u128] = self.buffer;
+        assert!(
+            self.len + data.len() <= buffer.len(),
+            "DMA chain buffer is full"
+        );
+        buffer[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        self
+    }
+
+    /// Append `data` after CNT tags, splitting it at `MAX_QWC` quadwords.
+    pub fn cnt(&mut self, data: &[u128]) -> &mut Self {
+        for chunk in data.chunks(MAX_QWC) {
+            self.tag(DmaTag::cnt(chunk.len()), 0).push(chunk);
+        }
+        self
+    }
+
+    /// Append REF tags for `data`, splitting it at `MAX_QWC` quadwords.
+    pub fn reference<T>(&mut self, data: &'a Aligned<A16, [T]>) -> &mut Self {
+        self.references(TagId::Ref, data)
+    }
+
+    /// Append stall-controlled REFS tags for `data`, splitting it at `MAX_QWC` quadwords.
+    pub fn refs<T>(&mut self, data: &'a Aligned<A16, [T]>) -> &mut Self {
+        self.references(TagId::Refs, data)
+    }
+
+    /// Append a CALL tag with `data` after it, to run `subroutine` and then carry on after `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `subroutine` is not exactly one level deeper than this chain.
+    pub fn call(&mut self, data: &[u128], subroutine: &'a Chain) -> &mut Self {
+        assert_eq!(
+            subroutine.depth,
+            self.depth + 1,
+            "Subroutines must be called from one level up"
+        );
+        writeback(subroutine.as_slice());
+        self.tag(DmaTag::call(data.len(), subroutine.address()), 0)
+            .push(data)
+    }
+
+    /// End a subroutine with a RET tag, with `data` after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a main chain.
+    pub fn ret(&mut self, data: &[u128]) -> &mut Self {
+        assert!(self.depth > 0, "Only subroutines can return");
+        self.tag(DmaTag::ret(data.len()), 0).push(data);
+        self.terminated = true;
+        self
+    }
+
+    /// End a main chain with an END tag, with `data` after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a subroutine.
+    pub fn end(&mut self, data: &[u128]) -> &mut Self {
+        assert!(self.depth == 0, "Subroutines must end with RET");
+        self.tag(DmaTag::end(data.len()), 0).push(data);
+        self.terminated = true;
+        self
+    }
+
+    /// End a main chain with a REFE tag for `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a subroutine, or `data` is longer than `MAX_QWC` quadwords.
+    pub fn refe<T>(&mut self, data: &'a Aligned<A16, [T]>) -> &mut Self {
+        assert!(self.depth == 0, "Subroutines must end with RET");
+        self.tag(DmaTag::refe(qwords(data), address(data)), 0);
+        writeback(data);
+        self.terminated = true;
+        self
+    }
+
+    /// Finish the chain.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain has not been ended with `end`, `refe` or `ret`.
+    pub fn finish(self) -> Chain<'a> {
+        assert!(self.terminated, "The DMA chain was never ended");
+        let start = self.buffer.as_mut_ptr();
+        // The chain starts at the start of the (aligned) buffer, so it is aligned too.
+        let data = unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start, self.len) as *mut Aligned<A16, [u128]>)
+        };
+        Chain {
+            data,
+            depth: self.depth,
+        }
+    }
+
+    /// Append `id` tags for `data`, splitting it at `MAX_QWC` quadwords, and write `data` back from
+    /// the data cache. The chain only records the address of `data`, so the caller must keep it
+    /// alive and unchanged for as long as the chain.
+    pub(crate) fn references<T>(&mut self, id: TagId, data: &Aligned<A16, [T]>) -> &mut Self {
+        let mut addr = address(data);
+        let mut remaining = qwords(data);
+        while remaining > 0 {
+            let qwc = remaining.min(MAX_QWC);
+            self.tag(DmaTag::new(id, qwc, addr), 0);
+            addr += (qwc * 16) as u32;
+            remaining -= qwc;
+        }
+        writeback(data);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_encoding() {
+        assert_eq!(u64::from(DmaTag::cnt(3)), 0x1000_0003);
+        assert_eq!(u64::from(DmaTag::end(0)), 0x7000_0000);
+        assert_eq!(
+            u64::from(DmaTag::reference(0x10, 0x0012_3450).with_irq(true)),
+            0x0012_3450_B000_0010
+        );
+        assert_eq!(
+            u64::from(DmaTag::next(1, 0x0000_0100).with_spr(true)),
+            0x8000_0100_2000_0001
+        );
+        assert_eq!(u64::from(DmaTag::ret(0).with_pce(3)), 0x6C00_0000);
+
+        let tag = DmaTag::call(5, 0x7FFF_FFF0);
+        assert_eq!(
+            (tag.id(), tag.qwc(), tag.addr()),
+            (TagId::Call, 5, 0x7FFF_FFF0)
+        );
+        assert_eq!(tag.to_qword(0x1234) >> 64, 0x1234);
+    }
+
+    #[test]
+    fn scratchpad_address_sets_spr() {
+        let tag = DmaTag::reference(2, physical_address(0x7000_0040));
+        assert_eq!(u64::from(tag), 0x8000_0040_3000_0002);
+        assert_eq!(tag.addr(), 0x8000_0040);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unaligned_address() {
+        DmaTag::reference(1, 0x1008);
+    }
+
+    #[test]
+    fn builder_layout() {
+        let data = Aligned([0u128; 3]);
+        let mut buffer = Aligned([0u128; 8]);
+        let mut chain = ChainBuilder::new(&mut buffer);
+        chain.cnt(&[0xAA, 0xBB]).reference(&data).end(&[0xCC]);
+        let chain = chain.finish();
+        let chain = chain.as_slice();
+
+        assert_eq!(chain.len(), 6);
+        assert_eq!(chain[0], DmaTag::cnt(2).to_qword(0));
+        assert_eq!(&chain[1..3], &[0xAA, 0xBB]);
+        let reference = DmaTag::from(chain[3] as u64);
+        assert_eq!((reference.id(), reference.qwc()), (TagId::Ref, 3));
+        assert_eq!(reference.addr(), physical_address(data.as_ptr() as usize));
+        assert_eq!(chain[4], DmaTag::end(1).to_qword(0));
+        assert_eq!(chain[5], 0xCC);
+    }
+
+    #[test]
+    fn calls_nest_one_level_at_a_time() {
+        let mut inner = Aligned([0u128; 2]);
+        let mut inner = ChainBuilder::subroutine(&mut inner, 2);
+        inner.ret(&[0x22]);
+        let inner = inner.finish();
+
+        let mut outer = Aligned([0u128; 4]);
+        let mut outer = ChainBuilder::subroutine(&mut outer, 1);
+        outer.call(&[], &inner).ret(&[]);
+        let outer = outer.finish();
+
+        let mut main = Aligned([0u128; 4]);
+        let mut main = ChainBuilder::new(&mut main);
+        main.call(&[0x11], &outer).end(&[]);
+        let main = main.finish();
+
+        let call = DmaTag::from(main.as_slice()[0] as u64);
+        assert_eq!((call.id(), call.qwc()), (TagId::Call, 1));
+        assert_eq!(call.addr(), outer.address());
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_deep() {
+        let mut buffer = Aligned([0u128; 2]);
+        ChainBuilder::subroutine(&mut buffer, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unterminated() {
+        let mut buffer = Aligned([0u128; 2]);
+        let mut chain = ChainBuilder::new(&mut buffer);
+        chain.cnt(&[1]);
+        chain.finish();
+    }
+}
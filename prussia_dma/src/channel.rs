@@ -0,0 +1,98 @@
+//! DMA channel registers.
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// DMA channel control register (CHCR).
+    ///
+    /// MOD, ASP and TAG are multi-bit fields; use the accessor methods to read and write them.
+    pub struct ChannelControl: u32 {
+        /// The direction of the transfer. 0 = to memory, 1 = from memory.
+        const DIR = 1;
+        /// Transfer mode field.
+        const MOD = 3 << 2;
+        /// Address stack pointer field: how many CALL tags are waiting for a RET.
+        const ASP = 3 << 4;
+        /// Whether DMAtags are transferred to the device in chain mode. 0 = not transferred,
+        /// 1 = transferred.
+        const TTE = 1 << 6;
+        /// Whether the IRQ bit of a DMAtag ends the transfer with an interrupt. 0 = ignored,
+        /// 1 = interrupt.
+        const TIE = 1 << 7;
+        /// Whether the channel is transferring. Set to start, and cleared by the DMAC when the
+        /// transfer ends. 0 = stopped, 1 = running.
+        const STR = 1 << 8;
+        /// Upper 16 bits of the last DMAtag read in chain mode.
+        const TAG = 0xFFFF << 16;
+    }
+}
+
+/// DMA channel transfer modes, as set in `ChannelControl::MOD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Transfer COUNT quadwords from ADDRESS. Supported on every channel.
+    Normal = 0,
+    /// Follow a chain of DMAtags. Supported on `SourceChain` and `DestChain` channels.
+    Chain = 1,
+    /// Transfer blocks of quadwords, skipping between them. Supported on `Scratchpad` channels.
+    Interleave = 2,
+}
+
+impl ChannelControl {
+    /// The transfer mode.
+    pub fn mode(&self) -> Mode {
+        match (self.bits >> 2) & 3 {
+            1 => Mode::Chain,
+            2 => Mode::Interleave,
+            _ => Mode::Normal,
+        }
+    }
+
+    /// Set the transfer mode.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.bits = (self.bits & !ChannelControl::MOD.bits) | (mode as u32) << 2;
+    }
+
+    /// The address stack pointer: 0, 1 or 2 CALL tags are waiting for a RET.
+    pub fn asp(&self) -> u8 {
+        ((self.bits >> 4) & 3) as u8
+    }
+
+    /// Set the address stack pointer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `asp` is greater than 2.
+    pub fn set_asp(&mut self, asp: u8) {
+        assert!(asp <= 2, "Address stack pointer {} is out of range", asp);
+        self.bits = (self.bits & !ChannelControl::ASP.bits) | (asp as u32) << 4;
+    }
+
+    /// The upper 16 bits of the last DMAtag read.
+    pub fn tag(&self) -> u16 {
+        (self.bits >> 16) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_control_fields() {
+        let mut control = ChannelControl::DIR | ChannelControl::STR;
+        control.set_mode(Mode::Chain);
+        control.set_asp(2);
+
+        assert_eq!(control.bits(), 0x125);
+        assert_eq!(control.mode(), Mode::Chain);
+        assert_eq!(control.asp(), 2);
+
+        control.set_mode(Mode::Interleave);
+        assert_eq!(control.bits(), 0x129);
+
+        let control = ChannelControl::from_bits_truncate(0x7000_0100);
+        assert_eq!(control.tag(), 0x7000);
+        assert!(control.contains(ChannelControl::STR));
+    }
+}
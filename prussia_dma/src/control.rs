@@ -1,4 +1,4 @@
-//! DMA Controller interrupt handling.
+//! DMA Controller global registers: control, priority, interrupts and suspension.
 
 use core::ptr;
 
@@ -77,3 +77,405 @@ impl Status {
         unsafe { ptr::write_volatile(DMAC_STAT, self.bits) }
     }
 }
+
+static mut DMAC_CTRL: *mut u32 = 0x1000_e000 as *mut u32;
+static mut DMAC_PCR: *mut u32 = 0x1000_e020 as *mut u32;
+static mut DMAC_SQWC: *mut u32 = 0x1000_e030 as *mut u32;
+static mut DMAC_RBSR: *mut u32 = 0x1000_e040 as *mut u32;
+static mut DMAC_RBOR: *mut u32 = 0x1000_e050 as *mut u32;
+static mut DMAC_STADR: *mut u32 = 0x1000_e060 as *mut u32;
+static mut DMAC_ENABLER: *mut u32 = 0x1000_f520 as *mut u32;
+static mut DMAC_ENABLEW: *mut u32 = 0x1000_f590 as *mut u32;
+
+bitflags! {
+    /// DMA Controller control register.
+    ///
+    /// MFD, STS, STD and RCYC are multi-bit fields; use the accessor methods to read and write
+    /// them.
+    pub struct Control: u32 {
+        /// Whether DMA transfers are enabled. 0 = disabled, 1 = enabled.
+        const DMAE = 1;
+        /// Whether cycle stealing is enabled, releasing the bus every RCYC cycles. 0 = disabled,
+        /// 1 = enabled.
+        const RELE = 1 << 1;
+        /// Memory FIFO drain channel field.
+        const MFD = 3 << 2;
+        /// Stall control source channel field.
+        const STS = 3 << 4;
+        /// Stall control drain channel field.
+        const STD = 3 << 6;
+        /// Release cycle field.
+        const RCYC = 7 << 8;
+    }
+}
+
+/// The channel draining the memory FIFO, as set in `Control::MFD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MfifoDrain {
+    /// The memory FIFO is not used.
+    None = 0,
+    /// VIF1 drains the memory FIFO.
+    Vif1 = 2,
+    /// The GIF drains the memory FIFO.
+    Gif = 3,
+}
+
+/// The channel whose writes advance the stall address, as set in `Control::STS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallSource {
+    /// No stall control.
+    None = 0,
+    /// SIF0 writes advance the stall address.
+    Sif0 = 1,
+    /// fromSPR writes advance the stall address.
+    SpramFrom = 2,
+    /// fromIPU writes advance the stall address.
+    IpuFrom = 3,
+}
+
+/// The channel which stalls on the stall address, as set in `Control::STD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallDrain {
+    /// No stall control.
+    None = 0,
+    /// VIF1 stalls.
+    Vif1 = 1,
+    /// The GIF stalls.
+    Gif = 2,
+    /// SIF1 stalls.
+    Sif1 = 3,
+}
+
+/// How many cycles pass between bus releases with `Control::RELE`, as set in `Control::RCYC`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReleaseCycle {
+    /// Release the bus every 8 cycles.
+    Cycles8 = 0,
+    /// Release the bus every 16 cycles.
+    Cycles16 = 1,
+    /// Release the bus every 32 cycles.
+    Cycles32 = 2,
+    /// Release the bus every 64 cycles.
+    Cycles64 = 3,
+    /// Release the bus every 128 cycles.
+    Cycles128 = 4,
+    /// Release the bus every 256 cycles.
+    Cycles256 = 5,
+}
+
+impl Control {
+    /// Load the DMA control register.
+    pub fn load() -> Self {
+        let control = unsafe { ptr::read_volatile(DMAC_CTRL) };
+        Control { bits: control }
+    }
+
+    /// Store the DMA control register.
+    pub fn store(self) {
+        unsafe { ptr::write_volatile(DMAC_CTRL, self.bits) }
+    }
+
+    /// The channel draining the memory FIFO.
+    pub fn mfd(&self) -> MfifoDrain {
+        match (self.bits >> 2) & 3 {
+            2 => MfifoDrain::Vif1,
+            3 => MfifoDrain::Gif,
+            _ => MfifoDrain::None,
+        }
+    }
+
+    /// Set the channel draining the memory FIFO.
+    pub fn set_mfd(&mut self, mfd: MfifoDrain) {
+        self.bits = (self.bits & !Control::MFD.bits) | (mfd as u32) << 2;
+    }
+
+    /// The stall control source channel.
+    pub fn sts(&self) -> StallSource {
+        match (self.bits >> 4) & 3 {
+            1 => StallSource::Sif0,
+            2 => StallSource::SpramFrom,
+            3 => StallSource::IpuFrom,
+            _ => StallSource::None,
+        }
+    }
+
+    /// Set the stall control source channel.
+    pub fn set_sts(&mut self, sts: StallSource) {
+        self.bits = (self.bits & !Control::STS.bits) | (sts as u32) << 4;
+    }
+
+    /// The stall control drain channel.
+    pub fn std(&self) -> StallDrain {
+        match (self.bits >> 6) & 3 {
+            1 => StallDrain::Vif1,
+            2 => StallDrain::Gif,
+            3 => StallDrain::Sif1,
+            _ => StallDrain::None,
+        }
+    }
+
+    /// Set the stall control drain channel.
+    pub fn set_std(&mut self, std: StallDrain) {
+        self.bits = (self.bits & !Control::STD.bits) | (std as u32) << 6;
+    }
+
+    /// The bus release cycle.
+    pub fn rcyc(&self) -> ReleaseCycle {
+        match (self.bits >> 8) & 7 {
+            1 => ReleaseCycle::Cycles16,
+            2 => ReleaseCycle::Cycles32,
+            3 => ReleaseCycle::Cycles64,
+            4 => ReleaseCycle::Cycles128,
+            5 => ReleaseCycle::Cycles256,
+            _ => ReleaseCycle::Cycles8,
+        }
+    }
+
+    /// Set the bus release cycle.
+    pub fn set_rcyc(&mut self, rcyc: ReleaseCycle) {
+        self.bits = (self.bits & !Control::RCYC.bits) | (rcyc as u32) << 8;
+    }
+}
+
+bitflags! {
+    /// DMA Controller priority control register.
+    pub struct PriorityControl: u32 {
+        /// Whether channel 0 is watched by COP0 condition (BC0F/BC0T). 0 = ignored, 1 = watched.
+        const CPC0 = 1;
+        /// Whether channel 1 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC1 = 1 << 1;
+        /// Whether channel 2 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC2 = 1 << 2;
+        /// Whether channel 3 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC3 = 1 << 3;
+        /// Whether channel 4 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC4 = 1 << 4;
+        /// Whether channel 5 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC5 = 1 << 5;
+        /// Whether channel 6 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC6 = 1 << 6;
+        /// Whether channel 7 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC7 = 1 << 7;
+        /// Whether channel 8 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC8 = 1 << 8;
+        /// Whether channel 9 is watched by COP0 condition. 0 = ignored, 1 = watched.
+        const CPC9 = 1 << 9;
+        /// Whether channel 0 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE0 = 1 << 16;
+        /// Whether channel 1 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE1 = 1 << 17;
+        /// Whether channel 2 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE2 = 1 << 18;
+        /// Whether channel 3 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE3 = 1 << 19;
+        /// Whether channel 4 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE4 = 1 << 20;
+        /// Whether channel 5 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE5 = 1 << 21;
+        /// Whether channel 6 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE6 = 1 << 22;
+        /// Whether channel 7 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE7 = 1 << 23;
+        /// Whether channel 8 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE8 = 1 << 24;
+        /// Whether channel 9 may transfer while PCE is set. 0 = disabled, 1 = enabled.
+        const CDE9 = 1 << 25;
+        /// Whether the CDE bits and DMAtag PCE fields control which channels run. 0 = all
+        /// channels run, 1 = controlled.
+        const PCE = 1 << 31;
+    }
+}
+
+impl PriorityControl {
+    /// Load the DMA priority control register.
+    pub fn load() -> Self {
+        let pcr = unsafe { ptr::read_volatile(DMAC_PCR) };
+        PriorityControl { bits: pcr }
+    }
+
+    /// Store the DMA priority control register.
+    pub fn store(self) {
+        unsafe { ptr::write_volatile(DMAC_PCR, self.bits) }
+    }
+}
+
+/// DMA Controller interleave size register, for Interleave mode on the scratchpad channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interleave {
+    /// Quadwords skipped after each block (SQWC).
+    pub skip: u8,
+    /// Quadwords transferred in each block (TQWC).
+    pub transfer: u8,
+}
+
+impl Interleave {
+    /// Load the DMA interleave size register.
+    pub fn load() -> Self {
+        let sqwc = unsafe { ptr::read_volatile(DMAC_SQWC) };
+        Interleave {
+            skip: sqwc as u8,
+            transfer: (sqwc >> 16) as u8,
+        }
+    }
+
+    /// Store the DMA interleave size register.
+    pub fn store(self) {
+        let sqwc = self.skip as u32 | (self.transfer as u32) << 16;
+        unsafe { ptr::write_volatile(DMAC_SQWC, sqwc) }
+    }
+}
+
+/// DMA Controller ring buffer size and offset registers, which place the memory FIFO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingBuffer {
+    /// The address of the ring buffer, aligned to its size (RBOR).
+    pub offset: u32,
+    /// The size of the ring buffer in bytes, a power of two of at least 16 (from RBSR).
+    pub size: u32,
+}
+
+impl RingBuffer {
+    /// Describe a ring buffer of `size` bytes at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not a power of two of at least 16, or `offset` is not aligned to it.
+    pub fn new(offset: u32, size: u32) -> Self {
+        assert!(
+            size.is_power_of_two() && size >= 16,
+            "Ring buffer size {:#x} is not a power of two",
+            size
+        );
+        assert!(
+            offset & (size - 1) == 0,
+            "Ring buffer offset {:#x} is not aligned to its size",
+            offset
+        );
+        RingBuffer { offset, size }
+    }
+
+    /// The RBSR value: the mask of address bits which wrap around within the buffer.
+    pub fn mask(&self) -> u32 {
+        (self.size - 16) & 0x7FFF_FFF0
+    }
+
+    /// Load the DMA ring buffer registers.
+    pub fn load() -> Self {
+        let (rbsr, rbor) =
+            unsafe { (ptr::read_volatile(DMAC_RBSR), ptr::read_volatile(DMAC_RBOR)) };
+        RingBuffer {
+            offset: rbor & 0x7FFF_FFFF,
+            size: (rbsr & 0x7FFF_FFF0) + 16,
+        }
+    }
+
+    /// Store the DMA ring buffer registers.
+    pub fn store(self) {
+        unsafe {
+            ptr::write_volatile(DMAC_RBSR, self.mask());
+            ptr::write_volatile(DMAC_RBOR, self.offset & 0x7FFF_FFFF);
+        }
+    }
+}
+
+/// Load the DMA stall address register: how far the stall control source channel has written.
+pub fn stall_address() -> u32 {
+    unsafe { ptr::read_volatile(DMAC_STADR) & 0x7FFF_FFFF }
+}
+
+/// Store the DMA stall address register.
+pub fn set_stall_address(address: u32) {
+    unsafe { ptr::write_volatile(DMAC_STADR, address & 0x7FFF_FFFF) }
+}
+
+bitflags! {
+    /// DMA Controller hold state register, read through D_ENABLER and written through D_ENABLEW.
+    pub struct Enable: u32 {
+        /// Whether all DMA transfers are suspended. 0 = running, 1 = suspended.
+        const CPND = 1 << 16;
+    }
+}
+
+impl Enable {
+    /// Load the DMA hold state register.
+    pub fn load() -> Self {
+        let enable = unsafe { ptr::read_volatile(DMAC_ENABLER) };
+        // Keep the reserved bits, which must be written back unchanged.
+        Enable { bits: enable }
+    }
+
+    /// Store the DMA hold state register.
+    pub fn store(self) {
+        unsafe { ptr::write_volatile(DMAC_ENABLEW, self.bits) }
+    }
+}
+
+/// Suspends every DMA channel until dropped, so channel registers can be changed safely.
+///
+/// Transfers in progress stop at the next quadword boundary and resume where they left off once
+/// the guard is dropped. Guards may be nested; dropping restores the previous state.
+///
+/// # Examples
+///
+/// ```
+/// use prussia_dma::Suspend;
+///
+/// fn reprogram() {
+///     let _suspend = Suspend::new();
+///     // Change channel registers here.
+/// }
+/// ```
+pub struct Suspend {
+    previous: Enable,
+}
+
+impl Suspend {
+    /// Suspend all DMA transfers.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let previous = Enable::load();
+        (previous | Enable::CPND).store();
+        Suspend { previous }
+    }
+}
+
+impl Drop for Suspend {
+    fn drop(&mut self) {
+        self.previous.store();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_fields() {
+        let mut control = Control::DMAE;
+        control.set_mfd(MfifoDrain::Gif);
+        control.set_sts(StallSource::IpuFrom);
+        control.set_std(StallDrain::Vif1);
+        control.set_rcyc(ReleaseCycle::Cycles256);
+
+        assert_eq!(control.bits(), 0x050 | 0x500 | 0xC | 1 | 0x20);
+        assert_eq!(control.mfd(), MfifoDrain::Gif);
+        assert_eq!(control.sts(), StallSource::IpuFrom);
+        assert_eq!(control.std(), StallDrain::Vif1);
+        assert_eq!(control.rcyc(), ReleaseCycle::Cycles256);
+
+        control.set_sts(StallSource::None);
+        assert_eq!(control.bits(), 0x540 | 0xC | 1);
+    }
+
+    #[test]
+    fn ring_buffer_mask() {
+        assert_eq!(RingBuffer::new(0x0010_0000, 0x1000).mask(), 0xFF0);
+        assert_eq!(RingBuffer::new(0, 16).mask(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn misaligned_ring_buffer() {
+        RingBuffer::new(0x0010_0800, 0x1000);
+    }
+}
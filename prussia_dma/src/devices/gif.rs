@@ -1,12 +1,32 @@
+use crate::control::{MfifoDrain, StallDrain};
 use crate::devices::traits;
 
 /// The GS Interface, for writing data for the Graphics Synthesizer to render later.
 pub struct Gif;
 
 impl traits::Address for Gif {
+    const CHANNEL: usize = 2;
     const CONTROL: *mut usize = 0x1000_a000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_a010 as *mut usize;
     const COUNT: *mut usize = 0x1000_a020 as *mut usize;
 }
 
 impl traits::WriteChannel for Gif {}
+
+impl traits::TagAddress for Gif {
+    const TAG_ADDRESS: *mut usize = 0x1000_a030 as *mut usize;
+}
+
+impl traits::SourceChain for Gif {}
+
+impl traits::FifoDrain for Gif {
+    const MFD: MfifoDrain = MfifoDrain::Gif;
+}
+
+impl traits::StallControlDrain for Gif {
+    const STD: StallDrain = StallDrain::Gif;
+}
+
+impl traits::CallStack for Gif {
+    const STACK_ADDRESS: [*mut usize; 2] = [0x1000_a040 as *mut usize, 0x1000_a050 as *mut usize];
+}
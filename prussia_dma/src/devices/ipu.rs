@@ -1,3 +1,4 @@
+use crate::control::StallSource;
 use crate::devices::traits;
 
 /// The Image Processing Unit, for sending MPEG2 frames to be decoded.
@@ -6,6 +7,7 @@ pub struct IpuFrom;
 pub struct IpuTo;
 
 impl traits::Address for IpuFrom {
+    const CHANNEL: usize = 3;
     const CONTROL: *mut usize = 0x1000_b000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_b010 as *mut usize;
     const COUNT: *mut usize = 0x1000_b020 as *mut usize;
@@ -13,10 +15,21 @@ impl traits::Address for IpuFrom {
 
 impl traits::ReadChannel for IpuFrom {}
 
+impl traits::StallControlSource for IpuFrom {
+    const STS: StallSource = StallSource::IpuFrom;
+}
+
 impl traits::Address for IpuTo {
+    const CHANNEL: usize = 4;
     const CONTROL: *mut usize = 0x1000_b400 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_b410 as *mut usize;
     const COUNT: *mut usize = 0x1000_b420 as *mut usize;
 }
 
 impl traits::WriteChannel for IpuTo {}
+
+impl traits::TagAddress for IpuTo {
+    const TAG_ADDRESS: *mut usize = 0x1000_b430 as *mut usize;
+}
+
+impl traits::SourceChain for IpuTo {}
@@ -1,3 +1,4 @@
+use crate::control::{StallDrain, StallSource};
 use crate::devices::traits;
 
 /// SBUS Interface 0, for reading data from the Input/Output Processor.
@@ -8,6 +9,7 @@ pub struct Sif1;
 pub struct Sif2;
 
 impl traits::Address for Sif0 {
+    const CHANNEL: usize = 5;
     const CONTROL: *mut usize = 0x1000_c000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_c010 as *mut usize;
     const COUNT: *mut usize = 0x1000_c020 as *mut usize;
@@ -15,7 +17,18 @@ impl traits::Address for Sif0 {
 
 impl traits::ReadChannel for Sif0 {}
 
+impl traits::TagAddress for Sif0 {
+    const TAG_ADDRESS: *mut usize = 0x1000_c030 as *mut usize;
+}
+
+impl traits::DestChain for Sif0 {}
+
+impl traits::StallControlSource for Sif0 {
+    const STS: StallSource = StallSource::Sif0;
+}
+
 impl traits::Address for Sif1 {
+    const CHANNEL: usize = 6;
     const CONTROL: *mut usize = 0x1000_c400 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_c410 as *mut usize;
     const COUNT: *mut usize = 0x1000_c420 as *mut usize;
@@ -23,7 +36,18 @@ impl traits::Address for Sif1 {
 
 impl traits::WriteChannel for Sif1 {}
 
+impl traits::TagAddress for Sif1 {
+    const TAG_ADDRESS: *mut usize = 0x1000_c430 as *mut usize;
+}
+
+impl traits::SourceChain for Sif1 {}
+
+impl traits::StallControlDrain for Sif1 {
+    const STD: StallDrain = StallDrain::Sif1;
+}
+
 impl traits::Address for Sif2 {
+    const CHANNEL: usize = 7;
     const CONTROL: *mut usize = 0x1000_c800 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_c810 as *mut usize;
     const COUNT: *mut usize = 0x1000_c820 as *mut usize;
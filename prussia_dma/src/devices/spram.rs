@@ -1,3 +1,4 @@
+use crate::control::StallSource;
 use crate::devices::traits;
 
 /// Scratchpad RAM, for fast data reads.
@@ -6,6 +7,7 @@ pub struct SpramFrom;
 pub struct SpramTo;
 
 impl traits::Address for SpramFrom {
+    const CHANNEL: usize = 8;
     const CONTROL: *mut usize = 0x1000_d000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_d010 as *mut usize;
     const COUNT: *mut usize = 0x1000_d020 as *mut usize;
@@ -13,10 +15,29 @@ impl traits::Address for SpramFrom {
 
 impl traits::ReadChannel for SpramFrom {}
 
+impl traits::StallControlSource for SpramFrom {
+    const STS: StallSource = StallSource::SpramFrom;
+}
+
+impl traits::Scratchpad for SpramFrom {
+    const SCRATCHPAD_ADDRESS: *mut usize = 0x1000_d080 as *mut usize;
+}
+
 impl traits::Address for SpramTo {
+    const CHANNEL: usize = 9;
     const CONTROL: *mut usize = 0x1000_d400 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_d410 as *mut usize;
     const COUNT: *mut usize = 0x1000_d420 as *mut usize;
 }
 
 impl traits::WriteChannel for SpramTo {}
+
+impl traits::TagAddress for SpramTo {
+    const TAG_ADDRESS: *mut usize = 0x1000_d430 as *mut usize;
+}
+
+impl traits::SourceChain for SpramTo {}
+
+impl traits::Scratchpad for SpramTo {
+    const SCRATCHPAD_ADDRESS: *mut usize = 0x1000_d480 as *mut usize;
+}
@@ -1,19 +1,134 @@
-// Whether a channel can be read from.
+use core::ptr;
+
+use crate::channel::ChannelControl;
+use crate::control::{MfifoDrain, StallDrain, StallSource};
+
+/// Whether a channel can be read from.
 pub trait ReadChannel {}
 
-// Whether a channel can be written to.
+/// Whether a channel can be written to.
 pub trait WriteChannel {}
 
-// Memory addresses for writing to a DMA channel. Not all channels have all addresses, so they are
-// marked as Option<u32>.
+/// Memory addresses for writing to a DMA channel, which every channel has. The registers other
+/// channels have are described by the traits below.
 pub trait Address {
-    // Metadata and status control word.
+    /// Channel number, as used by the CIS and CIM bits of `Status` and the DMAC interrupt causes.
+    const CHANNEL: usize;
+    /// Metadata and status control word (CHCR).
     const CONTROL: *mut usize;
-    // Memory address to read to/write from.
+    /// Memory address to read to/write from (MADR).
     const ADDRESS: *mut usize;
-    // Number of 128-bit quadwords to read/write.
+    /// Number of 128-bit quadwords to read/write (QWC).
     const COUNT: *mut usize;
-    // TAG_ADDRESS is used for devices that support DMA Chain Mode. However, we currently don't use
-    // DMA Chain Mode, so it is irrelevant for now.
-    // const TAG_ADDRESS: Option<u32>;
+
+    /// Load the channel control register.
+    fn control(&self) -> ChannelControl {
+        let control = unsafe { ptr::read_volatile(Self::CONTROL) };
+        ChannelControl::from_bits_truncate(control as u32)
+    }
+
+    /// Store the channel control register.
+    fn set_control(&mut self, control: ChannelControl) {
+        unsafe { ptr::write_volatile(Self::CONTROL, control.bits() as usize) }
+    }
+
+    /// Load the memory address register. Bit 31 selects scratchpad RAM.
+    fn address(&self) -> u32 {
+        unsafe { ptr::read_volatile(Self::ADDRESS) as u32 }
+    }
+
+    /// Store the memory address register.
+    fn set_address(&mut self, address: u32) {
+        unsafe { ptr::write_volatile(Self::ADDRESS, address as usize) }
+    }
+
+    /// Load the quadword count register.
+    fn count(&self) -> u16 {
+        unsafe { ptr::read_volatile(Self::COUNT) as u16 }
+    }
+
+    /// Store the quadword count register.
+    fn set_count(&mut self, count: u16) {
+        unsafe { ptr::write_volatile(Self::COUNT, count as usize) }
+    }
+}
+
+/// Channels with a tag address register, used in chain mode.
+pub trait TagAddress: Address {
+    /// Address of the next DMAtag to read (TADR).
+    const TAG_ADDRESS: *mut usize;
+
+    /// Load the tag address register.
+    fn tag_address(&self) -> u32 {
+        unsafe { ptr::read_volatile(Self::TAG_ADDRESS) as u32 }
+    }
+
+    /// Store the tag address register.
+    fn set_tag_address(&mut self, address: u32) {
+        unsafe { ptr::write_volatile(Self::TAG_ADDRESS, address as usize) }
+    }
+}
+
+/// Channels which can follow a chain of DMAtags in memory (Source Chain mode).
+pub trait SourceChain: TagAddress {}
+
+/// Channels which can write data placed by DMAtags read from the device (Destination Chain mode).
+pub trait DestChain: TagAddress {}
+
+/// Source Chain channels which support the CALL and RET DMAtags.
+pub trait CallStack: SourceChain {
+    /// Return addresses pushed by CALL tags (ASR0 and ASR1).
+    const STACK_ADDRESS: [*mut usize; 2];
+
+    /// Load address stack register `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than 1.
+    fn stack_address(&self, index: usize) -> u32 {
+        unsafe { ptr::read_volatile(Self::STACK_ADDRESS[index]) as u32 }
+    }
+
+    /// Store address stack register `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than 1.
+    fn set_stack_address(&mut self, index: usize, address: u32) {
+        unsafe { ptr::write_volatile(Self::STACK_ADDRESS[index], address as usize) }
+    }
+}
+
+/// The scratchpad RAM channels, which also support Interleave mode.
+pub trait Scratchpad: Address {
+    /// Address in scratchpad RAM to read to/write from (SADR).
+    const SCRATCHPAD_ADDRESS: *mut usize;
+
+    /// Load the scratchpad address register.
+    fn scratchpad_address(&self) -> u32 {
+        unsafe { ptr::read_volatile(Self::SCRATCHPAD_ADDRESS) as u32 }
+    }
+
+    /// Store the scratchpad address register. Only bits 4-13 are used.
+    fn set_scratchpad_address(&mut self, address: u32) {
+        unsafe { ptr::write_volatile(Self::SCRATCHPAD_ADDRESS, (address & 0x3FF0) as usize) }
+    }
+}
+
+/// Source Chain channels which can drain the memory FIFO.
+pub trait FifoDrain: SourceChain {
+    /// The `Control::MFD` value selecting this channel.
+    const MFD: MfifoDrain;
+}
+
+/// Channels writing to memory which can stall a drain channel (stall control sources).
+pub trait StallControlSource: ReadChannel + Address {
+    /// The `Control::STS` value selecting this channel.
+    const STS: StallSource;
+}
+
+/// Source Chain channels which can be stalled by a stall control source.
+pub trait StallControlDrain: SourceChain {
+    /// The `Control::STD` value selecting this channel.
+    const STD: StallDrain;
 }
@@ -1,3 +1,4 @@
+use crate::control::{MfifoDrain, StallDrain};
 use crate::devices::traits;
 
 /// VU Interface 0, for writing data to Vector Unit 0.
@@ -6,6 +7,7 @@ pub struct Vif0;
 pub struct Vif1;
 
 impl traits::Address for Vif0 {
+    const CHANNEL: usize = 0;
     const CONTROL: *mut usize = 0x1000_8000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_8010 as *mut usize;
     const COUNT: *mut usize = 0x1000_8020 as *mut usize;
@@ -13,7 +15,18 @@ impl traits::Address for Vif0 {
 
 impl traits::WriteChannel for Vif0 {}
 
+impl traits::TagAddress for Vif0 {
+    const TAG_ADDRESS: *mut usize = 0x1000_8030 as *mut usize;
+}
+
+impl traits::SourceChain for Vif0 {}
+
+impl traits::CallStack for Vif0 {
+    const STACK_ADDRESS: [*mut usize; 2] = [0x1000_8040 as *mut usize, 0x1000_8050 as *mut usize];
+}
+
 impl traits::Address for Vif1 {
+    const CHANNEL: usize = 1;
     const CONTROL: *mut usize = 0x1000_9000 as *mut usize;
     const ADDRESS: *mut usize = 0x1000_9010 as *mut usize;
     const COUNT: *mut usize = 0x1000_9020 as *mut usize;
@@ -21,3 +34,21 @@ impl traits::Address for Vif1 {
 
 impl traits::ReadChannel for Vif1 {}
 impl traits::WriteChannel for Vif1 {}
+
+impl traits::TagAddress for Vif1 {
+    const TAG_ADDRESS: *mut usize = 0x1000_9030 as *mut usize;
+}
+
+impl traits::SourceChain for Vif1 {}
+
+impl traits::FifoDrain for Vif1 {
+    const MFD: MfifoDrain = MfifoDrain::Vif1;
+}
+
+impl traits::StallControlDrain for Vif1 {
+    const STD: StallDrain = StallDrain::Vif1;
+}
+
+impl traits::CallStack for Vif1 {
+    const STACK_ADDRESS: [*mut usize; 2] = [0x1000_9040 as *mut usize, 0x1000_9050 as *mut usize];
+}
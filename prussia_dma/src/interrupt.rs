@@ -0,0 +1,131 @@
+//! Interrupt-driven DMA completion.
+//!
+//! `Transfer::wait` spins until the channel stops, which keeps the EE busy for the length of the
+//! transfer. When a channel finishes, the DMAC sets its CIS bit in `Status`, and if the matching
+//! CIM bit is set it raises INT1. Routing that interrupt to `dmac_handler` lets a transfer either
+//! call a function on completion (`Transfer::on_complete`) or put the waiting thread to sleep
+//! until then (`Transfer::sleep`), so other work can run in the meantime.
+
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(target_arch = "mips")]
+use prussia_bios::Event;
+
+use crate::control::Status;
+
+/// The number of DMA channels.
+pub const CHANNELS: usize = 10;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+#[cfg(target_arch = "mips")]
+#[allow(clippy::declare_interior_mutable_const)]
+const NOT_COMPLETED: Event<1> = Event::new();
+
+/// The `fn()` to call when each channel next completes, or 0.
+static CALLBACKS: [AtomicUsize; CHANNELS] = [NO_CALLBACK; CHANNELS];
+/// The completion interrupts seen on each channel, which the thread in `Transfer::sleep` waits
+/// for.
+#[cfg(target_arch = "mips")]
+static COMPLETIONS: [Event<1>; CHANNELS] = [NOT_COMPLETED; CHANNELS];
+
+/// A DMAC handler for transfer completion, for `Transfer::on_complete` and `Transfer::sleep`.
+///
+/// Register this for each channel's cause with `prussia_bios::add_dmac_handler` and enable it
+/// with `prussia_bios::enable_dmac`. Callbacks run inside the handler, so they must be short and
+/// only use the interrupt-safe (`i`-prefixed) BIOS calls.
+pub fn dmac_handler(cause: i32) -> i32 {
+    let channel = cause as usize;
+    if channel >= CHANNELS {
+        return 0;
+    }
+
+    // CIS is latched until cleared by writing 1 to it; writing 0 to CIM leaves the mask alone.
+    Status::from_bits_truncate(1 << channel).store();
+
+    #[cfg(target_arch = "mips")]
+    COMPLETIONS[channel].isignal();
+
+    // The R5900 has no atomic read-modify-write instructions, but interrupts are disabled here,
+    // so nothing can run between a load and a store.
+    let callback = CALLBACKS[channel].load(Ordering::Acquire);
+    if callback != 0 {
+        CALLBACKS[channel].store(0, Ordering::Release);
+        let callback: fn() = unsafe { mem::transmute(callback) };
+        callback();
+    }
+
+    0
+}
+
+/// The number of completion interrupts `dmac_handler` has seen on `channel`.
+///
+/// # Panics
+///
+/// Panics if `channel` is not a DMA channel number.
+#[cfg(target_arch = "mips")]
+pub fn completions(channel: usize) -> u32 {
+    COMPLETIONS[channel].count()
+}
+
+/// Call `callback` from `dmac_handler` when `channel` next completes, replacing any callback
+/// already set.
+pub(crate) fn set_callback(channel: usize, callback: fn()) {
+    CALLBACKS[channel].store(callback as usize, Ordering::Release);
+}
+
+/// Remove the callback for `channel`, returning it if `dmac_handler` has not taken it already.
+pub(crate) fn take_callback(channel: usize) -> Option<fn()> {
+    let callback = without_interrupts(|| {
+        let callback = CALLBACKS[channel].load(Ordering::Acquire);
+        CALLBACKS[channel].store(0, Ordering::Release);
+        callback
+    });
+    match callback {
+        0 => None,
+        callback => Some(unsafe { mem::transmute::<usize, fn()>(callback) }),
+    }
+}
+
+/// Put the current thread to sleep until `done` returns true, checking it again each time
+/// `channel` completes.
+#[cfg(target_arch = "mips")]
+pub(crate) fn sleep_until<F: FnMut() -> bool>(channel: usize, done: F) {
+    COMPLETIONS[channel].sleep_until(done);
+}
+
+/// Run `f` with interrupts disabled, so `dmac_handler` cannot run between its loads and stores.
+fn without_interrupts<R, F: FnOnce() -> R>(f: F) -> R {
+    #[cfg(target_arch = "mips")]
+    {
+        let mut result = None;
+        prussia_rt::interrupts::free(|| result = Some(f()));
+        result.unwrap()
+    }
+    #[cfg(not(target_arch = "mips"))]
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    static CALLED: AtomicBool = AtomicBool::new(false);
+
+    fn mark_called() {
+        CALLED.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn callback_is_taken_once() {
+        set_callback(3, mark_called);
+        let callback = take_callback(3).unwrap();
+        assert!(take_callback(3).is_none());
+
+        callback();
+        assert!(CALLED.load(Ordering::SeqCst));
+    }
+}
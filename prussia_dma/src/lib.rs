@@ -5,7 +5,14 @@
 //! the Input/Output Processor) and the Image Processing Unit (IPU).
 //!
 //! Most DMA transfers are represented through the `Transfer` struct, and can be created through
-//! the `Transfer::from_mem` and `Transfer::to_mem` functions.
+//! the `Transfer::from_mem` and `Transfer::to_mem` functions. For buffers which are not
+//! `'static`, `Transfer::from_mem_scoped` and `Transfer::to_mem_scoped` borrow the buffer until the
+//! transfer completes. Chains of DMAtags built with `chain::ChainBuilder` are transferred with
+//! `Transfer::chain`. Rather than spinning in `Transfer::wait`, a transfer can run a callback or
+//! sleep the waiting thread until it completes; see the `interrupt` module. The `scratchpad`
+//! module allocates buffers in scratchpad RAM and streams data through them, and the `mfifo`
+//! module streams them on to VIF1 or the GIF. The `stall` module lets one channel read data as
+//! another writes it. Packets for VIF0 and VIF1 are built with `vifcode::VifPacket`.
 //!
 //! Ownership of a channel is represented through the `Vif0`, `Vif1`, `Gif` (etc) types, which are
 //! moved into a `Transfer` while it is in progress to avoid multiple transfers on the same
@@ -39,13 +46,47 @@
 extern crate aligned;
 
 use aligned::{Aligned, A16};
-use core::{mem, ptr, sync::atomic};
+use core::{mem, sync::atomic};
 
+use crate::chain::Chain;
+
+pub mod chain;
+mod channel;
 mod control;
 mod devices;
+pub mod interrupt;
+pub mod mfifo;
+pub mod scratchpad;
+pub mod stall;
+pub mod vifcode;
+
+pub use crate::channel::{ChannelControl, Mode};
+pub use crate::control::{
+    set_stall_address, stall_address, Control, Enable, Interleave, MfifoDrain, PriorityControl,
+    ReleaseCycle, RingBuffer, StallDrain, StallSource, Status, Suspend,
+};
+pub use crate::devices::{
+    Address, CallStack, DestChain, FifoDrain, Gif, IpuFrom, IpuTo, ReadChannel, Scratchpad, Sif0,
+    Sif1, Sif2, SourceChain, SpramFrom, SpramTo, StallControlDrain, StallControlSource, TagAddress,
+    Vif0, Vif1, WriteChannel,
+};
+
+/// The address the DMAC uses for `address` in main memory or scratchpad RAM.
+///
+/// Main memory can be accessed by the CPU through cached, uncached (0x2000_0000) and uncached
+/// accelerated (0x3000_0000) aliases, as well as kernel segments; the DMAC only understands the
+/// physical address underneath. Scratchpad RAM is addressed by its offset with bit 31 (SPR) set,
+/// as MADR and DMAtag addresses expect.
+pub fn physical_address(address: usize) -> u32 {
+    if (scratchpad::BASE..scratchpad::BASE + scratchpad::SIZE).contains(&address) {
+        (address - scratchpad::BASE) as u32 | SPR
+    } else {
+        (address & 0x0FFF_FFFF) as u32
+    }
+}
 
-pub use crate::control::Status;
-pub use crate::devices::{Gif, IpuFrom, IpuTo, Sif0, Sif1, Sif2, SpramFrom, SpramTo, Vif0, Vif1};
+/// The bit of a DMA address selecting scratchpad RAM.
+const SPR: u32 = 1 << 31;
 
 /// Represents the channels of the DMA controller.
 pub struct Channels {
@@ -91,13 +132,11 @@ impl Channels {
     }
 
     /// Initialise a DMA channel.
-    fn init<T: devices::Address>(_dev: &mut T) {
-        unsafe {
-            // Zero the registers of the device.
-            ptr::write_volatile(T::CONTROL, 0);
-            ptr::write_volatile(T::ADDRESS, 0);
-            ptr::write_volatile(T::COUNT, 0);
-        }
+    fn init<T: devices::Address>(dev: &mut T) {
+        // Zero the registers of the device.
+        dev.set_control(ChannelControl::empty());
+        dev.set_address(0);
+        dev.set_count(0);
 
         atomic::compiler_fence(atomic::Ordering::SeqCst);
     }
@@ -164,7 +203,9 @@ impl<DEVICE: devices::ReadChannel + devices::Address, T> Transfer<DEVICE, T> {
     ///
     /// Reading from a write-only device is prevented at compile time through use of internal marker
     /// traits. Reading zero quadwords has no effect. Correct alignment is ensured through use of
-    /// `Aligned<A16, [T]>`.
+    /// `Aligned<A16, [T]>`. The data cache lines covering the destination are written back and
+    /// invalidated first; the CPU should not touch other data sharing those lines until the
+    /// transfer completes.
     ///
     /// # Examples
     ///
@@ -180,6 +221,34 @@ impl<DEVICE: devices::ReadChannel + devices::Address, T> Transfer<DEVICE, T> {
     pub fn to_mem(dev: DEVICE, data: &'static mut Aligned<A16, [T]>) -> Transfer<DEVICE, T> {
         Transfer::transfer(TransferDirection::ToMem, dev, data)
     }
+
+    /// Perform a DMA transfer from a device to a borrowed buffer, calling `f` while it runs and
+    /// waiting for it to complete before returning.
+    ///
+    /// Unlike `to_mem`, `data` can live anywhere, such as on the stack.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aligned::{Aligned, A16};
+    /// use prussia_dma::{Sif0, Transfer};
+    ///
+    /// fn receive(sif0: Sif0) -> (Sif0, u128) {
+    ///     let mut data = Aligned([0u128; 4]);
+    ///     let (sif0, ()) = Transfer::to_mem_scoped(sif0, &mut data, |_transfer| {
+    ///         // Do other work while the transfer runs.
+    ///     });
+    ///     (sif0, data[0])
+    /// }
+    /// ```
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_mem_scoped<R, F: FnOnce(&Transfer<DEVICE, T>) -> R>(
+        dev: DEVICE,
+        data: &mut Aligned<A16, [T]>,
+        f: F,
+    ) -> (DEVICE, R) {
+        Transfer::scoped(TransferDirection::ToMem, dev, data, f)
+    }
 }
 
 impl<DEVICE: devices::WriteChannel + devices::Address, T> Transfer<DEVICE, T> {
@@ -188,7 +257,7 @@ impl<DEVICE: devices::WriteChannel + devices::Address, T> Transfer<DEVICE, T> {
     ///
     /// Writing to a read-only device is prevented at compile time through use of internal marker
     /// traits. Writing zero quadwords has no effect. Correct alignment is ensured through use of
-    /// `Aligned<A16, T>`.
+    /// `Aligned<A16, T>`. The data cache lines covering the source are written back first.
     ///
     /// # Examples
     ///
@@ -203,40 +272,159 @@ impl<DEVICE: devices::WriteChannel + devices::Address, T> Transfer<DEVICE, T> {
     pub fn from_mem(dev: DEVICE, data: &'static mut Aligned<A16, [T]>) -> Transfer<DEVICE, T> {
         Transfer::transfer(TransferDirection::FromMem, dev, data)
     }
+
+    /// Perform a DMA transfer from a borrowed buffer to a device, calling `f` while it runs and
+    /// waiting for it to complete before returning.
+    ///
+    /// Unlike `from_mem`, `data` can live anywhere, such as on the stack.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aligned::{Aligned, A16};
+    /// use prussia_dma::{Gif, Transfer};
+    ///
+    /// fn send(gif: Gif, packet: &[u128; 4]) -> Gif {
+    ///     let mut data = Aligned(*packet);
+    ///     let (gif, ()) = Transfer::from_mem_scoped(gif, &mut data, |_| ());
+    ///     gif
+    /// }
+    /// ```
+    pub fn from_mem_scoped<R, F: FnOnce(&Transfer<DEVICE, T>) -> R>(
+        dev: DEVICE,
+        data: &mut Aligned<A16, [T]>,
+        f: F,
+    ) -> (DEVICE, R) {
+        Transfer::scoped(TransferDirection::FromMem, dev, data, f)
+    }
+}
+
+impl<DEVICE: devices::SourceChain> Transfer<DEVICE, u128> {
+    /// Start a Source Chain mode transfer, following the DMAtags of `chain` from its first tag, and
+    /// return a `Transfer` object bound to the chain.
+    ///
+    /// With `tte` set, each tag's quadword is sent to the device before its data; the VIF channels
+    /// treat the upper doubleword as two VIFcodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chain` is a subroutine rather than a main chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aligned::{Aligned, A16};
+    /// use prussia_dma::{chain::ChainBuilder, Gif, Transfer};
+    ///
+    /// fn send(gif: Gif, packet: &[u128]) -> Transfer<Gif, u128> {
+    ///     static mut BUFFER: Aligned<A16, [u128; 64]> = Aligned([0; 64]);
+    ///
+    ///     let mut chain = ChainBuilder::new(unsafe { &mut BUFFER });
+    ///     chain.cnt(packet).end(&[]);
+    ///     Transfer::chain(gif, chain.finish(), false)
+    /// }
+    /// ```
+    pub fn chain(mut dev: DEVICE, chain: Chain<'static>, tte: bool) -> Transfer<DEVICE, u128> {
+        assert_eq!(chain.depth(), 0, "Only main chains can be transferred");
+        let mut control = ChannelControl::DIR | ChannelControl::STR;
+        control.set_mode(Mode::Chain);
+        control.set(ChannelControl::TTE, tte);
+
+        // The data the tags refer to was written back as the chain was built.
+        chain::writeback(chain.as_slice());
+
+        // With a non-zero count, the DMAC would first transfer from ADDRESS as if by a CNT tag.
+        dev.set_count(0);
+        // Address of the first tag.
+        dev.set_tag_address(chain.address());
+        // Avoid compiler reordering.
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+        // Start the transfer.
+        dev.set_control(control);
+
+        Transfer {
+            data: chain.into_inner(),
+            dev,
+        }
+    }
+}
+
+/// Waits for a transfer of borrowed data when dropped, even when unwinding.
+struct WaitOnDrop<DEVICE: devices::Address, T: 'static>(Option<Transfer<DEVICE, T>>);
+
+impl<DEVICE: devices::Address, T> WaitOnDrop<DEVICE, T> {
+    fn finish(mut self) -> DEVICE {
+        self.0.take().unwrap().wait().0
+    }
+}
+
+impl<DEVICE: devices::Address, T> Drop for WaitOnDrop<DEVICE, T> {
+    fn drop(&mut self) {
+        if let Some(transfer) = self.0.take() {
+            transfer.wait();
+        }
+    }
 }
 
 impl<DEVICE: devices::Address, T: 'static> Transfer<DEVICE, T> {
-    fn transfer(
+    fn scoped<R, F: FnOnce(&Transfer<DEVICE, T>) -> R>(
         dir: TransferDirection,
         dev: DEVICE,
+        data: &mut Aligned<A16, [T]>,
+        f: F,
+    ) -> (DEVICE, R) {
+        // The transfer is waited for before this function returns or unwinds, and the guard is
+        // never leaked, so the borrow of data outlives it.
+        let data = unsafe { &mut *(data as *mut Aligned<A16, [T]>) };
+        let guard = WaitOnDrop(Some(Transfer::transfer(dir, dev, data)));
+        let result = f(guard.0.as_ref().unwrap());
+        (guard.finish(), result)
+    }
+
+    fn transfer(
+        dir: TransferDirection,
+        mut dev: DEVICE,
         data: &'static mut Aligned<A16, [T]>,
     ) -> Transfer<DEVICE, T> {
-        let address = data.as_ptr() as usize;
+        let address = physical_address(data.as_ptr() as usize);
         // This assumes that data is on a 16-byte boundary.
-        let qword_count: usize = (data.len() * mem::size_of::<T>()) / 16;
-        // The 1st bit of CHANNEL_CONTROL changes direction (we want to transfer from memory).
-        // The 9th bit of CHANNEL_CONTROL starts a transfer.
-        let control = unsafe { ptr::read_volatile(DEVICE::CONTROL) } | (1 << 8);
-        let control = match dir {
-            TransferDirection::ToMem => control & !1,
-            TransferDirection::FromMem => control | 1,
-        };
+        let qword_count = (data.len() * mem::size_of::<T>()) / 16;
+        assert!(
+            qword_count <= 0xFFFF,
+            "DMA transfers are limited to 65535 quadwords"
+        );
+        // DMA bypasses the data cache, so memory written by the CPU must be written back before DMA
+        // reads it, and no stale lines may hide or be written back over what DMA writes.
+        #[cfg(target_arch = "mips")]
+        {
+            let len = data.len() * mem::size_of::<T>();
+            if matches!(dir, TransferDirection::ToMem) {
+                prussia_rt::cache::writeback_invalidate_data(data.as_ptr() as usize, len);
+            } else {
+                prussia_rt::cache::writeback_data(data.as_ptr() as usize, len);
+            }
+        }
+        // Transfer COUNT quadwords from ADDRESS, in the requested direction.
+        let mut control = dev.control() | ChannelControl::STR;
+        control.set_mode(Mode::Normal);
+        control.set(
+            ChannelControl::DIR,
+            matches!(dir, TransferDirection::FromMem),
+        );
 
         // The EE User's Manual (5.10 Restrictions) states not to initiate a DMA transfer with a
         // qword count of zero (possibly because it transfers zero qwords?). Either way, avoid this
         // situation, and because we don't set the STR bit, drop() will see a finished transaction
         // immediately.
         if qword_count != 0 {
-            unsafe {
-                // Memory address to read data from.
-                ptr::write_volatile(DEVICE::ADDRESS, address);
-                // Number of 128-bit quadwords to read.
-                ptr::write_volatile(DEVICE::COUNT, qword_count);
-                // Avoid compiler reordering.
-                atomic::compiler_fence(atomic::Ordering::SeqCst);
-                // Start the transfer.
-                ptr::write_volatile(DEVICE::CONTROL, control);
-            }
+            // Memory address to read data from.
+            dev.set_address(address);
+            // Number of 128-bit quadwords to read.
+            dev.set_count(qword_count as u16);
+            // Avoid compiler reordering.
+            atomic::compiler_fence(atomic::Ordering::SeqCst);
+            // Start the transfer.
+            dev.set_control(control);
         }
 
         Transfer { data, dev }
@@ -246,10 +434,35 @@ impl<DEVICE: devices::Address, T: 'static> Transfer<DEVICE, T> {
     pub fn is_done(&self) -> bool {
         // Check if the STR bit is zero. If we skipped transferring zero qwords in
         // to_mem()/from_mem() then this will be zero anyway.
-        let control = unsafe { ptr::read_volatile(DEVICE::CONTROL) };
-        let control = control & (1 << 8);
+        !self.dev.control().contains(ChannelControl::STR)
+    }
+
+    /// Call `callback` once the transfer completes, from `interrupt::dmac_handler`, which must be
+    /// registered and enabled for this channel. If the transfer has already completed, `callback`
+    /// is called immediately instead.
+    ///
+    /// Only one callback is kept per channel; setting another replaces it.
+    pub fn on_complete(&self, callback: fn()) {
+        interrupt::set_callback(DEVICE::CHANNEL, callback);
+        // The transfer may have completed before the callback was set. Whichever of this and the
+        // handler takes the callback calls it, so it runs exactly once.
+        if self.is_done() {
+            if let Some(callback) = interrupt::take_callback(DEVICE::CHANNEL) {
+                callback();
+            }
+        }
+    }
+
+    /// Put the current thread to sleep until the transfer completes, returning the buffer used for
+    /// the transfer.
+    ///
+    /// The thread is woken by `interrupt::dmac_handler`, which must be registered and enabled for
+    /// this channel. Other threads run while this one sleeps.
+    pub fn sleep(self) -> (DEVICE, &'static mut Aligned<A16, [T]>) {
+        #[cfg(target_arch = "mips")]
+        interrupt::sleep_until(DEVICE::CHANNEL, || self.is_done());
 
-        control == 0
+        self.wait()
     }
 
     /// Wait for a transfer to complete, returning the buffer used for the transfer.
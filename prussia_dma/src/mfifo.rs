@@ -0,0 +1,230 @@
+//! The memory FIFO (MFIFO) between scratchpad RAM and VIF1 or the GIF.
+//!
+//! In MFIFO mode, the fromSPR channel writes into a ring buffer in main memory instead of a plain
+//! buffer, and the drain channel (VIF1 or the GIF) follows a DMAtag chain through that ring. The
+//! fromSPR MADR register is the producer pointer and the drain channel's TADR register is the
+//! consumer pointer; when they meet, the ring is empty and the drain channel waits for more data.
+//!
+//! Data pushed into the ring must therefore be a DMAtag chain. CNT tags, whose data follows the
+//! tag, wrap around the ring as expected; an END tag would stop the drain channel.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::mfifo::Mfifo;
+//! use prussia_dma::{Gif, SpramFrom};
+//!
+//! // The ring must be aligned to its size, which is more than `Aligned` provides.
+//! #[repr(align(16384))]
+//! struct Ring(Aligned<A16, [u128; 1024]>);
+//!
+//! fn stream(spr: SpramFrom, gif: Gif, list: &Aligned<A16, [u128]>) -> (SpramFrom, Gif) {
+//!     static mut RING: Ring = Ring(Aligned([0; 1024]));
+//!
+//!     let mut mfifo = Mfifo::new(unsafe { &mut RING.0 }, spr, gif);
+//!     // `list` is a chain of CNT tags in scratchpad RAM.
+//!     mfifo.push(list);
+//!     mfifo.flush();
+//!     let (_, spr, gif) = mfifo.into_inner();
+//!     (spr, gif)
+//! }
+//! ```
+
+use aligned::{Aligned, A16};
+use core::sync::atomic::{self, AtomicU32, Ordering};
+
+use crate::channel::{ChannelControl, Mode};
+use crate::control::{Control, MfifoDrain, RingBuffer, Status};
+use crate::devices::{Address, FifoDrain, Scratchpad};
+use crate::scratchpad;
+use crate::{physical_address, SpramFrom};
+
+/// The number of MEIS interrupts seen by `empty_handler`.
+static EMPTIES: AtomicU32 = AtomicU32::new(0);
+
+/// A DMAC handler counting MFIFO empty (MEIS) interrupts.
+///
+/// Register this for the MEIS cause with `prussia_bios::add_dmac_handler`, and enable the
+/// interrupt with `Mfifo::set_empty_interrupt`.
+pub fn empty_handler(_cause: i32) -> i32 {
+    // MEIS is latched until cleared by writing 1 to it.
+    Status::MEIS.store();
+    // This is the only writer, so a separate load and store is enough.
+    EMPTIES.store(
+        EMPTIES.load(Ordering::Relaxed).wrapping_add(1),
+        Ordering::Release,
+    );
+    0
+}
+
+/// The number of MEIS interrupts `empty_handler` has seen.
+pub fn empties() -> u32 {
+    EMPTIES.load(Ordering::Acquire)
+}
+
+/// The number of quadwords between `consumer` and `producer` in a ring of `size` bytes.
+fn used(producer: u32, consumer: u32, size: u32) -> usize {
+    (producer.wrapping_sub(consumer) & (size - 1)) as usize / 16
+}
+
+/// A memory FIFO, fed from scratchpad RAM and drained by `D`.
+pub struct Mfifo<D: FifoDrain> {
+    ring: &'static mut Aligned<A16, [u128]>,
+    spr: SpramFrom,
+    drain: D,
+}
+
+impl<D: FifoDrain> Mfifo<D> {
+    /// Set up the memory FIFO over `ring`, and start `drain` following it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `ring` is not a power of two, or `ring` is not aligned to its size.
+    pub fn new(ring: &'static mut Aligned<A16, [u128]>, mut spr: SpramFrom, mut drain: D) -> Self {
+        let address = physical_address(ring.as_ptr() as usize);
+        RingBuffer::new(address, (ring.len() * 16) as u32).store();
+
+        let mut control = Control::load();
+        control.set_mfd(D::MFD);
+        control.store();
+
+        // Both pointers start at the beginning of the ring, which is empty.
+        spr.set_address(address);
+        drain.set_tag_address(address);
+        drain.set_count(0);
+
+        let mut chcr = ChannelControl::DIR | ChannelControl::STR;
+        chcr.set_mode(Mode::Chain);
+        // Avoid compiler reordering.
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+        drain.set_control(chcr);
+
+        Mfifo { ring, spr, drain }
+    }
+
+    /// The size of the ring in bytes.
+    fn size(&self) -> u32 {
+        (self.ring.len() * 16) as u32
+    }
+
+    /// The address the next quadword will be written to (fromSPR MADR).
+    pub fn producer(&self) -> u32 {
+        self.spr.address()
+    }
+
+    /// The address of the next DMAtag the drain channel will read (TADR).
+    pub fn consumer(&self) -> u32 {
+        self.drain.tag_address()
+    }
+
+    /// The number of quadwords waiting to be drained.
+    pub fn len(&self) -> usize {
+        used(self.producer(), self.consumer(), self.size())
+    }
+
+    /// Whether the drain channel has caught up with the producer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most quadwords the ring can hold. One quadword is kept free, as a full ring would look
+    /// empty.
+    pub fn capacity(&self) -> usize {
+        self.ring.len() - 1
+    }
+
+    /// The number of quadwords which can be pushed without overwriting undrained data.
+    pub fn free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Whether the fromSPR channel is still copying the last push into the ring.
+    pub fn is_busy(&self) -> bool {
+        self.spr.control().contains(ChannelControl::STR)
+    }
+
+    /// Start copying `data` from scratchpad RAM into the ring, if the last push has finished and
+    /// there is room. Returns whether the copy was started.
+    ///
+    /// `data` must not be changed until `is_busy` returns false.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is not empty and not in scratchpad RAM.
+    pub fn try_push(&mut self, data: &Aligned<A16, [u128]>) -> bool {
+        if data.is_empty() {
+            return true;
+        }
+        let offset = scratchpad::offset(data);
+        if self.is_busy() || self.free() < data.len() {
+            return false;
+        }
+
+        // MADR continues from the end of the last push, wrapping around the ring.
+        self.spr.set_scratchpad_address(offset);
+        self.spr.set_count(data.len() as u16);
+        // Avoid compiler reordering.
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+        // A Normal mode transfer.
+        self.spr.set_control(ChannelControl::STR);
+        true
+    }
+
+    /// Copy `data` from scratchpad RAM into the ring, waiting for the last push to finish and for
+    /// the drain channel to make room.
+    ///
+    /// `data` must not be changed until `is_busy` returns false.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is larger than the ring, or is not in scratchpad RAM.
+    pub fn push(&mut self, data: &Aligned<A16, [u128]>) {
+        assert!(
+            data.len() <= self.capacity(),
+            "{} quadwords do not fit in the ring",
+            data.len()
+        );
+        while !self.try_push(data) {}
+    }
+
+    /// Wait until everything pushed has been drained.
+    pub fn flush(&self) {
+        while self.is_busy() || !self.is_empty() {}
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+
+    /// Enable or disable the MEIS interrupt, raised when the ring becomes empty.
+    pub fn set_empty_interrupt(&mut self, enabled: bool) {
+        // Writing 1 to MEIM toggles it.
+        if Status::load().contains(Status::MEIM) != enabled {
+            Status::MEIM.store();
+        }
+    }
+
+    /// Wait for the ring to drain, stop the drain channel and leave MFIFO mode, returning the ring
+    /// and the channels.
+    pub fn into_inner(mut self) -> (&'static mut Aligned<A16, [u128]>, SpramFrom, D) {
+        self.flush();
+        self.drain.set_control(ChannelControl::empty());
+
+        let mut control = Control::load();
+        control.set_mfd(MfifoDrain::None);
+        control.store();
+
+        (self.ring, self.spr, self.drain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn used_wraps_around_the_ring() {
+        assert_eq!(used(0x1000, 0x1000, 0x1000), 0);
+        assert_eq!(used(0x1040, 0x1000, 0x1000), 4);
+        // The producer has wrapped around to the start of the ring.
+        assert_eq!(used(0x1010, 0x1FF0, 0x1000), 2);
+    }
+}
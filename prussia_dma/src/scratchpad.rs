@@ -0,0 +1,334 @@
+//! Scratchpad RAM (SPR) buffers and double-buffered streaming.
+//!
+//! The EE has 16 KiB of scratchpad RAM mapped at 0x7000_0000. It is as fast as the data cache, and
+//! the toSPR and fromSPR channels move data between it and main memory without involving the CPU.
+//! `Scratchpad` hands out buffers in it, and `PingPong` runs the classic streaming loop: while the
+//! CPU processes one half of a scratchpad buffer, the other half is filled from main memory, and
+//! processed halves are written back out, or pushed on to VIF1 or the GIF through a memory FIFO.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::scratchpad::{PingPong, Scratchpad};
+//! use prussia_dma::{SpramFrom, SpramTo};
+//!
+//! fn double(to: SpramTo, from: SpramFrom, data: &mut Aligned<A16, [u128]>) -> (SpramTo, SpramFrom) {
+//!     let mut spr = Scratchpad::take().unwrap();
+//!     let mut ping_pong = PingPong::new(&mut spr, to, 256).unwrap();
+//!     let from = ping_pong.run(from, data, |chunk| {
+//!         for qword in chunk.iter_mut() {
+//!             *qword <<= 1;
+//!         }
+//!     });
+//!     (ping_pong.into_inner(), from)
+//! }
+//! ```
+
+use aligned::{Aligned, A16};
+use core::{mem, ptr};
+
+use crate::devices::{FifoDrain, Scratchpad as ScratchpadChannel};
+use crate::mfifo::Mfifo;
+use crate::{SpramFrom, SpramTo, Transfer};
+
+/// The address of scratchpad RAM, as seen by the CPU.
+pub const BASE: usize = 0x7000_0000;
+
+/// The size of scratchpad RAM in bytes.
+pub const SIZE: usize = 16 * 1024;
+
+/// A bump allocator over scratchpad RAM.
+///
+/// Buffers are never freed, so each one can be handed out with a `'static` lifetime.
+pub struct Scratchpad {
+    next: usize,
+}
+
+impl Scratchpad {
+    /// Return the scratchpad RAM, *once*.
+    pub fn take() -> Option<Self> {
+        static mut TAKEN: bool = false;
+        unsafe {
+            if !TAKEN {
+                TAKEN = true;
+                Some(Scratchpad { next: 0 })
+            } else {
+                None
+            }
+        }
+    }
+
+    /// The number of bytes not yet handed out.
+    pub fn remaining(&self) -> usize {
+        SIZE - self.next
+    }
+
+    /// Reserve `bytes` bytes, rounded up to a quadword, returning their offset.
+    fn reserve(&mut self, bytes: usize) -> Option<usize> {
+        let bytes = bytes.checked_add(15)? & !15;
+        if bytes > self.remaining() {
+            return None;
+        }
+        let offset = self.next;
+        self.next += bytes;
+        Some(offset)
+    }
+
+    /// Allocate `len` elements of `T` in scratchpad RAM, each set to `value`, or `None` if it is
+    /// full.
+    pub fn alloc<T: Copy>(
+        &mut self,
+        len: usize,
+        value: T,
+    ) -> Option<&'static mut Aligned<A16, [T]>> {
+        assert!(
+            mem::align_of::<T>() <= 16,
+            "Scratchpad buffers are 16-byte aligned"
+        );
+        let offset = self.reserve(len.checked_mul(mem::size_of::<T>())?)?;
+        let start = (BASE + offset) as *mut T;
+        unsafe {
+            for i in 0..len {
+                ptr::write(start.add(i), value);
+            }
+            // The region is quadword aligned and was never handed out before.
+            Some(&mut *(ptr::slice_from_raw_parts_mut(start, len) as *mut Aligned<A16, [T]>))
+        }
+    }
+}
+
+/// The offset of `buffer` within scratchpad RAM, as written to the SADR register.
+///
+/// # Panics
+///
+/// Panics if `buffer` is not in scratchpad RAM.
+pub fn offset<T>(buffer: &Aligned<A16, [T]>) -> u32 {
+    let address = buffer.as_ptr() as usize;
+    assert!(
+        (BASE..BASE + SIZE).contains(&address),
+        "Buffer at {:#x} is not in scratchpad RAM",
+        address
+    );
+    (address - BASE) as u32
+}
+
+/// Start copying `data` from main memory to `offset` bytes into scratchpad RAM.
+pub fn to_scratchpad<T>(
+    mut dev: SpramTo,
+    data: &'static mut Aligned<A16, [T]>,
+    offset: u32,
+) -> Transfer<SpramTo, T> {
+    dev.set_scratchpad_address(offset);
+    Transfer::from_mem(dev, data)
+}
+
+/// Start copying from `offset` bytes into scratchpad RAM to `data` in main memory.
+pub fn from_scratchpad<T>(
+    mut dev: SpramFrom,
+    offset: u32,
+    data: &'static mut Aligned<A16, [T]>,
+) -> Transfer<SpramFrom, T> {
+    dev.set_scratchpad_address(offset);
+    Transfer::to_mem(dev, data)
+}
+
+/// Extend the lifetime of `len` quadwords of `data` from `start`, for a transfer which is waited
+/// for before `data` is used again.
+unsafe fn chunk(data: &mut [u128], start: usize, len: usize) -> &'static mut Aligned<A16, [u128]> {
+    let start = data.as_mut_ptr().add(start);
+    &mut *(ptr::slice_from_raw_parts_mut(start, len) as *mut Aligned<A16, [u128]>)
+}
+
+/// Where `PingPong` sends each processed half.
+trait Sink {
+    /// Wait until the last half sent is no longer being read.
+    fn wait(&mut self);
+
+    /// Start sending the first `len` quadwords of `half`, which were loaded from `start`
+    /// quadwords into `data`.
+    fn send(&mut self, data: &mut [u128], half: &Aligned<A16, [u128]>, start: usize, len: usize);
+}
+
+/// Write processed halves back over the data they were loaded from.
+struct WriteBack {
+    from: Option<SpramFrom>,
+    draining: Option<Transfer<SpramFrom, u128>>,
+}
+
+impl Sink for WriteBack {
+    fn wait(&mut self) {
+        if let Some(transfer) = self.draining.take() {
+            self.from = Some(transfer.wait().0);
+        }
+    }
+
+    fn send(&mut self, data: &mut [u128], half: &Aligned<A16, [u128]>, start: usize, len: usize) {
+        let result = unsafe { chunk(data, start, len) };
+        let from = self.from.take().unwrap();
+        self.draining = Some(from_scratchpad(from, offset(half), result));
+    }
+}
+
+/// Push processed halves into a memory FIFO.
+struct Push<'a, D: FifoDrain> {
+    mfifo: &'a mut Mfifo<D>,
+}
+
+impl<'a, D: FifoDrain> Sink for Push<'a, D> {
+    fn wait(&mut self) {
+        while self.mfifo.is_busy() {}
+    }
+
+    fn send(&mut self, _data: &mut [u128], half: &Aligned<A16, [u128]>, _start: usize, len: usize) {
+        // The prefix of a scratchpad buffer is still quadword aligned.
+        let prefix = unsafe {
+            &*(ptr::slice_from_raw_parts(half.as_ptr(), len) as *const Aligned<A16, [u128]>)
+        };
+        self.mfifo.push(prefix);
+    }
+}
+
+/// Double-buffered processing of main memory through scratchpad RAM.
+pub struct PingPong {
+    halves: [&'static mut Aligned<A16, [u128]>; 2],
+    to: Option<SpramTo>,
+}
+
+impl PingPong {
+    /// Allocate two halves of `qwords` quadwords each from `spr`, or return `None` if it is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `qwords` is zero.
+    pub fn new(spr: &mut Scratchpad, to: SpramTo, qwords: usize) -> Option<Self> {
+        assert!(qwords > 0, "Ping-pong halves must not be empty");
+        let first = spr.alloc(qwords, 0u128)?;
+        let second = spr.alloc(qwords, 0u128)?;
+        Some(PingPong {
+            halves: [first, second],
+            to: Some(to),
+        })
+    }
+
+    /// The number of quadwords in each half.
+    pub fn half_len(&self) -> usize {
+        self.halves[0].len()
+    }
+
+    /// Stream `data` through scratchpad RAM, calling `process` on each chunk of up to `half_len`
+    /// quadwords in turn and writing the results back over `data` with `from`.
+    ///
+    /// While `process` runs on one half, the next chunk is loaded into the other, so the CPU only
+    /// waits for DMA when `process` is faster than the transfers.
+    pub fn run<F: FnMut(&mut [u128])>(
+        &mut self,
+        from: SpramFrom,
+        data: &mut Aligned<A16, [u128]>,
+        mut process: F,
+    ) -> SpramFrom {
+        let mut sink = WriteBack {
+            from: Some(from),
+            draining: None,
+        };
+        self.stream(data, &mut sink, |half, len| {
+            process(&mut half[..len]);
+            len
+        });
+        sink.from.unwrap()
+    }
+
+    /// Stream `data` through scratchpad RAM as `run` does, but push the results into `mfifo`,
+    /// such as to build a display list for the GIF on the scratchpad.
+    ///
+    /// `process` is given a whole half, with the chunk loaded into its first quadwords, and the
+    /// length of the chunk. It returns the number of quadwords at the start of the half to push,
+    /// which must form a chain of CNT tags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `process` returns more than `half_len` quadwords, or more than `mfifo` can hold.
+    pub fn run_mfifo<D: FifoDrain, F: FnMut(&mut [u128], usize) -> usize>(
+        &mut self,
+        mfifo: &mut Mfifo<D>,
+        data: &mut Aligned<A16, [u128]>,
+        process: F,
+    ) {
+        self.stream(data, &mut Push { mfifo }, process);
+    }
+
+    fn stream<S: Sink, F: FnMut(&mut [u128], usize) -> usize>(
+        &mut self,
+        data: &mut [u128],
+        sink: &mut S,
+        mut process: F,
+    ) {
+        let total = data.len();
+        let half_len = self.half_len();
+        let chunks = total.div_ceil(half_len);
+        let offsets = [offset(self.halves[0]), offset(self.halves[1])];
+        let chunk_len = |i: usize| half_len.min(total - i * half_len);
+
+        if chunks == 0 {
+            return;
+        }
+
+        let mut to = self.to.take();
+
+        let first = unsafe { chunk(data, 0, chunk_len(0)) };
+        let mut filling = Some(to_scratchpad(to.take().unwrap(), first, offsets[0]));
+
+        for i in 0..chunks {
+            let half = i % 2;
+            let len = chunk_len(i);
+            to = filling.take().map(|transfer| transfer.wait().0);
+
+            if i + 1 < chunks {
+                // The other half is refilled once its results have been sent.
+                sink.wait();
+                let next = unsafe { chunk(data, (i + 1) * half_len, chunk_len(i + 1)) };
+                filling = Some(to_scratchpad(to.take().unwrap(), next, offsets[1 - half]));
+            }
+
+            let sent = process(&mut (**self.halves[half])[..], len);
+            assert!(
+                sent <= half_len,
+                "{} quadwords do not fit in a ping-pong half",
+                sent
+            );
+
+            sink.wait();
+            sink.send(data, self.halves[half], i * half_len, sent);
+        }
+
+        sink.wait();
+        self.to = to;
+    }
+
+    /// Return the toSPR channel.
+    pub fn into_inner(self) -> SpramTo {
+        self.to.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_rounds_to_quadwords() {
+        let mut spr = Scratchpad { next: 0 };
+        assert_eq!(spr.reserve(4), Some(0));
+        assert_eq!(spr.reserve(32), Some(16));
+        assert_eq!(spr.remaining(), SIZE - 48);
+    }
+
+    #[test]
+    fn reserve_fails_when_full() {
+        let mut spr = Scratchpad { next: 0 };
+        assert_eq!(spr.reserve(SIZE - 16), Some(0));
+        assert_eq!(spr.reserve(32), None);
+        assert_eq!(spr.reserve(16), Some(SIZE - 16));
+        assert_eq!(spr.remaining(), 0);
+    }
+}
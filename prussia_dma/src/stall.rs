@@ -0,0 +1,140 @@
+//! Stall control between a source and a drain channel.
+//!
+//! A stall control source channel (SIF0, fromSPR or fromIPU) writes to memory, and the DMAC
+//! records how far it has got in the stall address register. A drain channel (VIF1, the GIF or
+//! SIF1) following a DMAtag chain stalls at any REFS tag whose data lies beyond the stall address,
+//! so it never reads data which has not been written yet. This lets the two transfers run at the
+//! same time through one buffer.
+//!
+//! Only one source and one drain can be paired at a time, which `Stall` enforces by owning both
+//! channels. The trait bounds on `Stall` restrict it to legal combinations.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::stall::Stall;
+//! use prussia_dma::{Gif, IpuFrom};
+//!
+//! fn decode_and_draw(ipu: IpuFrom, gif: Gif) -> (IpuFrom, Gif) {
+//!     static mut IMAGE: Aligned<A16, [u128; 1024]> = Aligned([0; 1024]);
+//!     static mut TAGS: Aligned<A16, [u128; 8]> = Aligned([0; 8]);
+//!
+//!     // The GIF stalls until the IPU has written each part of the image.
+//!     let stall = Stall::new(ipu, gif);
+//!     let transfer = stall.start(unsafe { &mut IMAGE }, unsafe { &mut TAGS }, false);
+//!     let (stall, _, _) = transfer.wait();
+//!     stall.into_inner()
+//! }
+//! ```
+
+use aligned::{Aligned, A16};
+
+use crate::chain::{ChainBuilder, TagId};
+use crate::control::{set_stall_address, Control, StallDrain, StallSource, Status};
+use crate::devices::{StallControlDrain, StallControlSource};
+use crate::{physical_address, Transfer};
+
+/// A stall control source and drain channel pair.
+pub struct Stall<S: StallControlSource, D: StallControlDrain> {
+    source: S,
+    drain: D,
+}
+
+impl<S: StallControlSource, D: StallControlDrain> Stall<S, D> {
+    /// Select `source` and `drain` for stall control.
+    pub fn new(source: S, drain: D) -> Self {
+        let mut control = Control::load();
+        control.set_sts(S::STS);
+        control.set_std(D::STD);
+        control.store();
+
+        Stall { source, drain }
+    }
+
+    /// The source channel, for setting up registers such as the scratchpad address before
+    /// starting.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Start the source writing to `data`, and the drain following a chain of REFS tags reading
+    /// `data` back, which is built in `tags`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tags` is too small for the chain, which takes one quadword for each
+    /// `chain::MAX_QWC` quadwords of `data`, plus one for the END tag.
+    pub fn start<T>(
+        self,
+        data: &'static mut Aligned<A16, [T]>,
+        tags: &'static mut Aligned<A16, [u128]>,
+        tte: bool,
+    ) -> StallTransfer<S, D, T> {
+        // The chain only holds the address of `data`, which the source owns until both finish.
+        let mut chain = ChainBuilder::new(tags);
+        chain.references(TagId::Refs, data).end(&[]);
+
+        // Nothing has been written yet, so the drain stalls at the first REFS tag.
+        set_stall_address(physical_address(data.as_ptr() as usize));
+        let drain = Transfer::chain(self.drain, chain.finish(), tte);
+        let source = Transfer::to_mem(self.source, data);
+        StallTransfer { source, drain }
+    }
+
+    /// Whether the drain channel has stalled since the last call, clearing the record.
+    pub fn take_stalled(&mut self) -> bool {
+        let stalled = Status::load().contains(Status::SIS);
+        if stalled {
+            // SIS is latched until cleared by writing 1 to it.
+            Status::SIS.store();
+        }
+        stalled
+    }
+
+    /// Enable or disable the SIS interrupt, raised when the drain channel stalls.
+    pub fn set_stall_interrupt(&mut self, enabled: bool) {
+        // Writing 1 to SIM toggles it.
+        if Status::load().contains(Status::SIM) != enabled {
+            Status::SIM.store();
+        }
+    }
+
+    /// Turn off stall control, returning the channels.
+    pub fn into_inner(self) -> (S, D) {
+        let mut control = Control::load();
+        control.set_sts(StallSource::None);
+        control.set_std(StallDrain::None);
+        control.store();
+
+        (self.source, self.drain)
+    }
+}
+
+/// An in-progress stall-controlled pair of transfers.
+pub struct StallTransfer<S: StallControlSource, D: StallControlDrain, T: 'static> {
+    source: Transfer<S, T>,
+    drain: Transfer<D, u128>,
+}
+
+impl<S: StallControlSource, D: StallControlDrain, T> StallTransfer<S, D, T> {
+    /// Returns true if both transfers have completed.
+    pub fn is_done(&self) -> bool {
+        self.source.is_done() && self.drain.is_done()
+    }
+
+    /// Wait for both transfers to complete, returning the channel pair, the data buffer and the
+    /// chain buffer.
+    #[allow(clippy::type_complexity)]
+    pub fn wait(
+        self,
+    ) -> (
+        Stall<S, D>,
+        &'static mut Aligned<A16, [T]>,
+        &'static mut Aligned<A16, [u128]>,
+    ) {
+        let (source, data) = self.source.wait();
+        let (drain, chain) = self.drain.wait();
+        (Stall { source, drain }, data, chain)
+    }
+}
@@ -0,0 +1,699 @@
+//! VIFcodes and VIF packet construction.
+//!
+//! VIF0 and VIF1 receive a stream of 32-bit words. Each command is a VIFcode, which may be
+//! followed by data, and which is laid out as:
+//!
+//! | Bits  | Field                                       |
+//! |-------|---------------------------------------------|
+//! | 0-15  | IMMEDIATE                                   |
+//! | 16-23 | NUM                                         |
+//! | 24-30 | CMD                                         |
+//! | 31    | I, interrupt after the command              |
+//!
+//! Some data must be aligned in the stream: MPG microcode to a doubleword, and DIRECT/DIRECTHL
+//! GIF packets to a quadword. `VifPacket` pads with NOPs before such codes, and pads the end of
+//! the packet to a whole quadword, so that a finished packet can be sent on its own or placed
+//! after a DMAtag with `chain::ChainBuilder`. With CHCR.TTE set, the upper doubleword of each
+//! DMAtag is sent as two VIFcodes, which `VifCode::pair` builds.
+//!
+//! OFFSET, BASE, MSKPATH3, FLUSH, FLUSHA, DIRECT and DIRECTHL are only understood by VIF1.
+//!
+//! # Examples
+//!
+//! ```
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::chain::ChainBuilder;
+//! use prussia_dma::vifcode::{UnpackFormat, VifPacket};
+//!
+//! fn upload(vif1: prussia_dma::Vif1, vertices: &[u32]) -> prussia_dma::Vif1 {
+//!     static mut PACKET: Aligned<A16, [u128; 64]> = Aligned([0; 64]);
+//!     static mut BUFFER: Aligned<A16, [u128; 4]> = Aligned([0; 4]);
+//!
+//!     let mut packet = VifPacket::new(unsafe { &mut PACKET });
+//!     packet
+//!         .stcycl(1, 1)
+//!         .unpack(UnpackFormat::V4_32, 0, vertices)
+//!         .mscal(0);
+//!     let packet = packet.finish();
+//!
+//!     let mut chain = ChainBuilder::new(unsafe { &mut BUFFER });
+//!     chain.reference(packet).end(&[]);
+//!
+//!     let (vif1, _) = prussia_dma::Transfer::chain(vif1, chain.finish(), false).wait();
+//!     vif1
+//! }
+//! ```
+
+use core::ptr;
+
+use aligned::{Aligned, A16};
+
+/// The most instructions a single MPG can upload.
+pub const MAX_MPG: usize = 256;
+
+/// The most vectors a single UNPACK can write.
+pub const MAX_UNPACK: usize = 256;
+
+/// The most quadwords a single DIRECT or DIRECTHL can transfer.
+pub const MAX_DIRECT: usize = 0x10000;
+
+/// VIFcode commands, other than UNPACK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Do nothing.
+    Nop = 0x00,
+    /// Set the CYCLE register.
+    Stcycl = 0x01,
+    /// Set the double-buffer offset (VIF1 only).
+    Offset = 0x02,
+    /// Set the double-buffer base address (VIF1 only).
+    Base = 0x03,
+    /// Set the data address for the next microprogram.
+    Itop = 0x04,
+    /// Set the UNPACK addition mode.
+    Stmod = 0x05,
+    /// Mask or unmask GIF PATH3 (VIF1 only).
+    Mskpath3 = 0x06,
+    /// Set the MARK register.
+    Mark = 0x07,
+    /// Wait for the microprogram to end.
+    Flushe = 0x10,
+    /// Wait for the microprogram and GIF PATH1/PATH2 to end (VIF1 only).
+    Flush = 0x11,
+    /// Wait for the microprogram and all GIF paths to end (VIF1 only).
+    Flusha = 0x13,
+    /// Start a microprogram.
+    Mscal = 0x14,
+    /// Start a microprogram once the GIF PATH1/PATH2 have ended.
+    Mscalf = 0x15,
+    /// Continue the microprogram from where it stopped.
+    Mscnt = 0x17,
+    /// Set the write mask, from the following word.
+    Stmask = 0x20,
+    /// Set the row registers, from the following four words.
+    Strow = 0x30,
+    /// Set the column registers, from the following four words.
+    Stcol = 0x31,
+    /// Upload microcode.
+    Mpg = 0x4A,
+    /// Send a GIF packet through PATH2 (VIF1 only).
+    Direct = 0x50,
+    /// Send a GIF packet through PATH2, waiting for PATH3 IMAGE transfers (VIF1 only).
+    Directhl = 0x51,
+}
+
+/// How UNPACK adds the row registers to unpacked data, set by STMOD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnpackMode {
+    /// Write data unchanged.
+    Normal = 0,
+    /// Add the row registers to the data.
+    Offset = 1,
+    /// Add the row registers to the data, and store the result back into them.
+    Difference = 2,
+}
+
+/// The layout of UNPACK data: the number of elements in each vector and the size of each element.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnpackFormat {
+    /// One 32-bit element.
+    S_32 = 0x0,
+    /// One 16-bit element.
+    S_16 = 0x1,
+    /// One 8-bit element.
+    S_8 = 0x2,
+    /// Two 32-bit elements.
+    V2_32 = 0x4,
+    /// Two 16-bit elements.
+    V2_16 = 0x5,
+    /// Two 8-bit elements.
+    V2_8 = 0x6,
+    /// Three 32-bit elements.
+    V3_32 = 0x8,
+    /// Three 16-bit elements.
+    V3_16 = 0x9,
+    /// Three 8-bit elements.
+    V3_8 = 0xA,
+    /// Four 32-bit elements.
+    V4_32 = 0xC,
+    /// Four 16-bit elements.
+    V4_16 = 0xD,
+    /// Four 8-bit elements.
+    V4_8 = 0xE,
+    /// A 16-bit RGBA 5:5:5:1 colour.
+    V4_5 = 0xF,
+}
+
+impl UnpackFormat {
+    /// The number of bits in each vector of data.
+    pub fn bits(self) -> usize {
+        if self == UnpackFormat::V4_5 {
+            return 16;
+        }
+        let vn = (self as usize >> 2) + 1;
+        let vl = self as usize & 3;
+        vn * (32 >> vl)
+    }
+
+    /// The number of words of data for `num` vectors, including padding to a whole word.
+    pub fn words(self, num: usize) -> usize {
+        (num * self.bits()).div_ceil(32)
+    }
+
+    /// The number of vectors in `words` words of data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `words` is not a whole number of vectors.
+    pub fn vectors(self, words: usize) -> usize {
+        let num = words * 32 / self.bits();
+        assert_eq!(
+            self.words(num),
+            words,
+            "UNPACK data must be a whole number of vectors"
+        );
+        num
+    }
+}
+
+/// A VIFcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VifCode(u32);
+
+impl VifCode {
+    /// Create a code with the given command, NUM and IMMEDIATE fields.
+    pub fn new(cmd: Command, num: u8, immediate: u16) -> Self {
+        VifCode((cmd as u32) << 24 | (num as u32) << 16 | immediate as u32)
+    }
+
+    /// A NOP.
+    pub fn nop() -> Self {
+        VifCode::new(Command::Nop, 0, 0)
+    }
+
+    /// A STCYCL, writing `cl` vectors for every `wl` vectors of UNPACK data.
+    pub fn stcycl(cl: u8, wl: u8) -> Self {
+        VifCode::new(Command::Stcycl, 0, (wl as u16) << 8 | cl as u16)
+    }
+
+    /// An OFFSET, setting the double-buffer offset to `offset` quadwords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` does not fit in 10 bits.
+    pub fn offset(offset: u16) -> Self {
+        VifCode::new(Command::Offset, 0, ten_bits(offset))
+    }
+
+    /// A BASE, setting the double-buffer base to `base` quadwords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base` does not fit in 10 bits.
+    pub fn base(base: u16) -> Self {
+        VifCode::new(Command::Base, 0, ten_bits(base))
+    }
+
+    /// An ITOP, setting the data address read by the next microprogram with XITOP.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addr` does not fit in 10 bits.
+    pub fn itop(addr: u16) -> Self {
+        VifCode::new(Command::Itop, 0, ten_bits(addr))
+    }
+
+    /// A STMOD, setting the UNPACK addition mode.
+    pub fn stmod(mode: UnpackMode) -> Self {
+        VifCode::new(Command::Stmod, 0, mode as u16)
+    }
+
+    /// A MSKPATH3, masking GIF PATH3 transfers if `masked` is set.
+    pub fn mskpath3(masked: bool) -> Self {
+        VifCode::new(Command::Mskpath3, 0, (masked as u16) << 15)
+    }
+
+    /// A MARK, setting the MARK register to `mark`.
+    pub fn mark(mark: u16) -> Self {
+        VifCode::new(Command::Mark, 0, mark)
+    }
+
+    /// A FLUSHE.
+    pub fn flushe() -> Self {
+        VifCode::new(Command::Flushe, 0, 0)
+    }
+
+    /// A FLUSH.
+    pub fn flush() -> Self {
+        VifCode::new(Command::Flush, 0, 0)
+    }
+
+    /// A FLUSHA.
+    pub fn flusha() -> Self {
+        VifCode::new(Command::Flusha, 0, 0)
+    }
+
+    /// A MSCAL, starting the microprogram at instruction `addr`.
+    pub fn mscal(addr: u16) -> Self {
+        VifCode::new(Command::Mscal, 0, addr)
+    }
+
+    /// A MSCALF, starting the microprogram at instruction `addr` once the GIF is idle.
+    pub fn mscalf(addr: u16) -> Self {
+        VifCode::new(Command::Mscalf, 0, addr)
+    }
+
+    /// A MSCNT.
+    pub fn mscnt() -> Self {
+        VifCode::new(Command::Mscnt, 0, 0)
+    }
+
+    /// A STMASK, which must be followed by the mask.
+    pub fn stmask() -> Self {
+        VifCode::new(Command::Stmask, 0, 0)
+    }
+
+    /// A STROW, which must be followed by the four row values.
+    pub fn strow() -> Self {
+        VifCode::new(Command::Strow, 0, 0)
+    }
+
+    /// A STCOL, which must be followed by the four column values.
+    pub fn stcol() -> Self {
+        VifCode::new(Command::Stcol, 0, 0)
+    }
+
+    /// An MPG, which must be followed by `len` instructions to upload to instruction `addr`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero or more than `MAX_MPG`.
+    pub fn mpg(len: usize, addr: u16) -> Self {
+        assert!(
+            (1..=MAX_MPG).contains(&len),
+            "MPG of {} instructions is out of range",
+            len
+        );
+        // A NUM of 0 means 256.
+        VifCode::new(Command::Mpg, len as u8, addr)
+    }
+
+    /// A DIRECT, which must be followed by a GIF packet of `qwc` quadwords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `qwc` is zero or more than `MAX_DIRECT`.
+    pub fn direct(qwc: usize) -> Self {
+        VifCode::new(Command::Direct, 0, direct_qwc(qwc))
+    }
+
+    /// A DIRECTHL, which must be followed by a GIF packet of `qwc` quadwords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `qwc` is zero or more than `MAX_DIRECT`.
+    pub fn directhl(qwc: usize) -> Self {
+        VifCode::new(Command::Directhl, 0, direct_qwc(qwc))
+    }
+
+    /// An UNPACK, which must be followed by the data for `num` vectors in `format`, to be written
+    /// from quadword `addr` of VU memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` is zero or more than `MAX_UNPACK`, or `addr` does not fit in 10 bits.
+    pub fn unpack(format: UnpackFormat, num: usize, addr: u16) -> Self {
+        assert!(
+            (1..=MAX_UNPACK).contains(&num),
+            "UNPACK of {} vectors is out of range",
+            num
+        );
+        // A NUM of 0 means 256.
+        VifCode(
+            0x60 << 24 | (format as u32) << 24 | (num as u8 as u32) << 16 | ten_bits(addr) as u32,
+        )
+    }
+
+    /// Sign-extend UNPACK data if `unsigned` is clear, or zero-extend it if set.
+    pub fn with_unsigned(self, unsigned: bool) -> Self {
+        VifCode(self.0 & !(1 << 14) | (unsigned as u32) << 14)
+    }
+
+    /// Add the double-buffer address (TOPS) to the UNPACK address.
+    pub fn with_tops(self, tops: bool) -> Self {
+        VifCode(self.0 & !(1 << 15) | (tops as u32) << 15)
+    }
+
+    /// Apply the write mask set by STMASK to UNPACK data.
+    pub fn with_mask(self, masked: bool) -> Self {
+        VifCode(self.0 & !(1 << 28) | (masked as u32) << 28)
+    }
+
+    /// Raise an interrupt after this code, stalling the VIF until it is acknowledged.
+    pub fn with_irq(self, irq: bool) -> Self {
+        VifCode(self.0 & !(1 << 31) | (irq as u32) << 31)
+    }
+
+    /// The command field, including the UNPACK format and mask bits.
+    pub fn cmd(&self) -> u8 {
+        (self.0 >> 24) as u8 & 0x7F
+    }
+
+    /// The NUM field.
+    pub fn num(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    /// The IMMEDIATE field.
+    pub fn immediate(&self) -> u16 {
+        self.0 as u16
+    }
+
+    /// Whether the code raises an interrupt.
+    pub fn irq(&self) -> bool {
+        self.0 & (1 << 31) != 0
+    }
+
+    /// Two codes as the upper doubleword of a DMAtag, for `chain::DmaTag::to_qword`. `first` is
+    /// sent first.
+    pub fn pair(first: VifCode, second: VifCode) -> u64 {
+        first.0 as u64 | (second.0 as u64) << 32
+    }
+}
+
+impl From<VifCode> for u32 {
+    fn from(code: VifCode) -> Self {
+        code.0
+    }
+}
+
+impl From<u32> for VifCode {
+    fn from(bits: u32) -> Self {
+        VifCode(bits)
+    }
+}
+
+/// Check that `value` fits in a 10-bit IMMEDIATE field.
+fn ten_bits(value: u16) -> u16 {
+    assert!(value < 0x400, "{:#x} does not fit in 10 bits", value);
+    value
+}
+
+/// The IMMEDIATE field of a DIRECT or DIRECTHL of `qwc` quadwords.
+fn direct_qwc(qwc: usize) -> u16 {
+    assert!(
+        (1..=MAX_DIRECT).contains(&qwc),
+        "DIRECT of {} quadwords is out of range",
+        qwc
+    );
+    // An IMMEDIATE of 0 means 65536.
+    qwc as u16
+}
+
+/// A VIF packet under construction.
+///
+/// The packet is written into a caller-provided buffer, and `finish` returns the part of the
+/// buffer that was used, padded to a whole quadword with NOPs.
+pub struct VifPacket<'a> {
+    buffer: &'a mut Aligned<A16, [u128]>,
+    len: usize,
+}
+
+impl<'a> VifPacket<'a> {
+    /// Start a new, empty packet in `buffer`.
+    pub fn new(buffer: &'a mut Aligned<A16, [u128]>) -> Self {
+        VifPacket { buffer, len: 0 }
+    }
+
+    /// The number of words written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of words that can still be written.
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() * 4 - self.len
+    }
+
+    /// Append raw words, such as the data following a code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer does not have room for `words`.
+    pub fn words(&mut self, words: &[u32]) -> &mut Self {
+        assert!(words.len() <= self.remaining(), "VIF packet buffer is full");
+        let buffer: &mut [u128] = self.buffer;
+        for &word in words {
+            let qword = &mut buffer[self.len / 4];
+            let shift = (self.len % 4) * 32;
+            *qword = *qword & !(0xFFFF_FFFF << shift) | (word as u128) << shift;
+            self.len += 1;
+        }
+        self
+    }
+
+    /// Append a code. The caller is responsible for appending any data it needs.
+    pub fn code(&mut self, code: VifCode) -> &mut Self {
+        self.words(&[code.into()])
+    }
+
+    /// Append NOPs until the next word is at a multiple of `words` words.
+    pub fn align(&mut self, words: usize) -> &mut Self {
+        while !self.len.is_multiple_of(words) {
+            self.code(VifCode::nop());
+        }
+        self
+    }
+
+    /// Append a NOP.
+    pub fn nop(&mut self) -> &mut Self {
+        self.code(VifCode::nop())
+    }
+
+    /// Append a STCYCL.
+    pub fn stcycl(&mut self, cl: u8, wl: u8) -> &mut Self {
+        self.code(VifCode::stcycl(cl, wl))
+    }
+
+    /// Append an OFFSET.
+    pub fn offset(&mut self, offset: u16) -> &mut Self {
+        self.code(VifCode::offset(offset))
+    }
+
+    /// Append a BASE.
+    pub fn base(&mut self, base: u16) -> &mut Self {
+        self.code(VifCode::base(base))
+    }
+
+    /// Append an ITOP.
+    pub fn itop(&mut self, addr: u16) -> &mut Self {
+        self.code(VifCode::itop(addr))
+    }
+
+    /// Append a STMOD.
+    pub fn stmod(&mut self, mode: UnpackMode) -> &mut Self {
+        self.code(VifCode::stmod(mode))
+    }
+
+    /// Append a MSKPATH3.
+    pub fn mskpath3(&mut self, masked: bool) -> &mut Self {
+        self.code(VifCode::mskpath3(masked))
+    }
+
+    /// Append a MARK.
+    pub fn mark(&mut self, mark: u16) -> &mut Self {
+        self.code(VifCode::mark(mark))
+    }
+
+    /// Append a FLUSHE.
+    pub fn flushe(&mut self) -> &mut Self {
+        self.code(VifCode::flushe())
+    }
+
+    /// Append a FLUSH.
+    pub fn flush(&mut self) -> &mut Self {
+        self.code(VifCode::flush())
+    }
+
+    /// Append a FLUSHA.
+    pub fn flusha(&mut self) -> &mut Self {
+        self.code(VifCode::flusha())
+    }
+
+    /// Append a MSCAL.
+    pub fn mscal(&mut self, addr: u16) -> &mut Self {
+        self.code(VifCode::mscal(addr))
+    }
+
+    /// Append a MSCALF.
+    pub fn mscalf(&mut self, addr: u16) -> &mut Self {
+        self.code(VifCode::mscalf(addr))
+    }
+
+    /// Append a MSCNT.
+    pub fn mscnt(&mut self) -> &mut Self {
+        self.code(VifCode::mscnt())
+    }
+
+    /// Append a STMASK setting the write mask to `mask`.
+    pub fn stmask(&mut self, mask: u32) -> &mut Self {
+        self.code(VifCode::stmask()).words(&[mask])
+    }
+
+    /// Append a STROW setting the row registers to `row`.
+    pub fn strow(&mut self, row: [u32; 4]) -> &mut Self {
+        self.code(VifCode::strow()).words(&row)
+    }
+
+    /// Append a STCOL setting the column registers to `col`.
+    pub fn stcol(&mut self, col: [u32; 4]) -> &mut Self {
+        self.code(VifCode::stcol()).words(&col)
+    }
+
+    /// Append MPGs uploading `code` from instruction `addr`, splitting it at `MAX_MPG`
+    /// instructions.
+    pub fn mpg(&mut self, addr: u16, code: &[u64]) -> &mut Self {
+        let mut addr = addr;
+        for chunk in code.chunks(MAX_MPG) {
+            // The microcode must start on a doubleword, straight after the MPG.
+            if self.len.is_multiple_of(2) {
+                self.nop();
+            }
+            self.code(VifCode::mpg(chunk.len(), addr));
+            for &instruction in chunk {
+                self.words(&[instruction as u32, (instruction >> 32) as u32]);
+            }
+            addr += chunk.len() as u16;
+        }
+        self
+    }
+
+    /// Append DIRECTs sending the GIF packet `data`, splitting it at `MAX_DIRECT` quadwords.
+    pub fn direct(&mut self, data: &[u128]) -> &mut Self {
+        self.directs(data, VifCode::direct)
+    }
+
+    /// Append DIRECTHLs sending the GIF packet `data`, splitting it at `MAX_DIRECT` quadwords.
+    pub fn directhl(&mut self, data: &[u128]) -> &mut Self {
+        self.directs(data, VifCode::directhl)
+    }
+
+    /// Append an UNPACK of `data`, in `format`, to quadword `addr` of VU memory. The number of
+    /// vectors is worked out from the length of `data`, assuming a STCYCL with `cl >= wl`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not hold a whole number of vectors, up to `MAX_UNPACK`.
+    pub fn unpack(&mut self, format: UnpackFormat, addr: u16, data: &[u32]) -> &mut Self {
+        let num = format.vectors(data.len());
+        self.unpack_code(VifCode::unpack(format, num, addr), data)
+    }
+
+    /// Append `code`, which should be an UNPACK, followed by `data`.
+    ///
+    /// This does not check that `data` matches `code`; prefer `unpack` where it can be used.
+    pub fn unpack_code(&mut self, code: VifCode, data: &[u32]) -> &mut Self {
+        self.code(code).words(data)
+    }
+
+    /// Finish the packet, padding it to a whole quadword with NOPs and returning the written part
+    /// of the buffer.
+    pub fn finish(mut self) -> &'a mut Aligned<A16, [u128]> {
+        self.align(4);
+        let start = self.buffer.as_mut_ptr();
+        // The written part of the buffer begins at the same (aligned) address as the buffer, so
+        // it can be reinterpreted as a shorter aligned slice.
+        unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start, self.len / 4) as *mut Aligned<A16, [u128]>)
+        }
+    }
+
+    fn directs(&mut self, data: &[u128], code: fn(usize) -> VifCode) -> &mut Self {
+        for chunk in data.chunks(MAX_DIRECT) {
+            // The GIF packet must start on a quadword, straight after the code.
+            while self.len % 4 != 3 {
+                self.nop();
+            }
+            self.code(code(chunk.len()));
+            for &qword in chunk {
+                self.words(&[
+                    qword as u32,
+                    (qword >> 32) as u32,
+                    (qword >> 64) as u32,
+                    (qword >> 96) as u32,
+                ]);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_encoding() {
+        assert_eq!(u32::from(VifCode::stcycl(1, 4)), 0x0100_0401);
+        assert_eq!(u32::from(VifCode::mskpath3(true)), 0x0600_8000);
+        assert_eq!(u32::from(VifCode::mscal(0x20).with_irq(true)), 0x9400_0020);
+        assert_eq!(u32::from(VifCode::mpg(256, 0x10)), 0x4A00_0010);
+        assert_eq!(u32::from(VifCode::direct(MAX_DIRECT)), 0x5000_0000);
+        assert_eq!(
+            u32::from(
+                VifCode::unpack(UnpackFormat::V4_32, 3, 0x3FF)
+                    .with_tops(true)
+                    .with_unsigned(true)
+                    .with_mask(true)
+            ),
+            0x7C03_C3FF
+        );
+        assert_eq!(
+            VifCode::pair(VifCode::flush(), VifCode::nop()),
+            0x0000_0000_1100_0000
+        );
+    }
+
+    #[test]
+    fn unpack_sizes() {
+        assert_eq!(UnpackFormat::S_8.words(5), 2);
+        assert_eq!(UnpackFormat::V3_16.words(3), 5);
+        assert_eq!(UnpackFormat::V4_32.words(2), 8);
+        assert_eq!(UnpackFormat::V4_5.words(3), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn partial_unpack() {
+        let mut buffer = Aligned([0u128; 4]);
+        VifPacket::new(&mut buffer).unpack(UnpackFormat::V3_32, 0, &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn payload_alignment() {
+        let mut buffer = Aligned([0u128; 8]);
+        let mut packet = VifPacket::new(&mut buffer);
+        packet
+            .mark(1)
+            .mpg(0, &[0x1111_1111_2222_2222])
+            .direct(&[0x33])
+            .unpack(UnpackFormat::S_16, 4, &[0x44]);
+        let packet: &[u128] = packet.finish();
+
+        assert_eq!(
+            packet,
+            [
+                0x1111_1111_2222_2222_4A01_0000_0700_0001,
+                0x5000_0001_0000_0000_0000_0000_0000_0000,
+                0x33,
+                0x0000_0000_0000_0000_0000_0044_6102_0004,
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Colour lookup tables (CLUTs) for indexed textures.
+//!
+//! PSMT8 and PSMT4 textures store an index per texel, which the GS looks up in a CLUT of 256 or 16
+//! colours. The CLUT lives in GS local memory like any other image, and is loaded into the GS's
+//! CLUT buffer when TEX0 is written with a CLD (CLUT load) value asking for it.
+//!
+//! There are two storage modes for the CLUT in local memory:
+//!
+//! - CSM1: the CLUT is an 8x2 (16 colours) or 16x16 (256 colours) image. For 256 colours, the
+//!   entries do not appear in order: in each group of 32 entries, entries 8-15 and 16-23 swap
+//!   places. Both PSMCT32 and PSMCT16 colours are supported.
+//! - CSM2: the CLUT is a single row of colours in order, and TEXCLUT gives its position. Only
+//!   PSMCT16 colours are supported.
+//!
+//! `Clut` converts a palette of colours in index order into either layout, ready to upload.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use prussia_gs::clut::{Clut, ClutLoad, Csm};
+//! use prussia_gs::registers::{Psm, TEX0};
+//! use prussia_gs::vram::Allocator;
+//!
+//! fn grey_ramp(gif: prussia_dma::Gif, vram: &mut Allocator) -> (prussia_dma::Gif, TEX0) {
+//!     let colours: [u32; 256] = core::array::from_fn(|i| 0x8000_0000 | (i as u32 * 0x01_0101));
+//!     let mut clut = Clut::ct32(&colours, Csm::Csm1);
+//!
+//!     let (width, height) = clut.size();
+//!     let clut_memory = vram.alloc_texture(width as u32, height as u32, Psm::PSMCT32).unwrap();
+//!     let cbp = clut_memory.tbp();
+//!     let gif = clut.upload(gif, cbp);
+//!
+//!     let tex0 = clut.tex0(TEX0::new().with_psm(Psm::PSMT8 as u8), cbp, ClutLoad::Load);
+//!     (gif, tex0)
+//! }
+//! ```
+
+use aligned::{Aligned, A16};
+use prussia_dma::Gif;
+
+use crate::gif::GifPacket;
+use crate::registers::{Psm, TEX0, TEXCLUT};
+use crate::transfer::{upload, Rect, Upload};
+use crate::vram::buffer_width;
+
+/// CLUT storage modes, as used in `TEX0::csm`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Csm {
+    /// An 8x2 or 16x16 image, with 256-colour entries swizzled.
+    Csm1 = 0,
+    /// A single row of colours, positioned by TEXCLUT.
+    Csm2 = 1,
+}
+
+/// When the CLUT buffer is loaded from local memory, as used in `TEX0::cld`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClutLoad {
+    /// Keep the current CLUT buffer contents.
+    Keep = 0,
+    /// Load the CLUT buffer.
+    Load = 1,
+    /// Load the CLUT buffer, and copy CBP to CBP0.
+    LoadCbp0 = 2,
+    /// Load the CLUT buffer, and copy CBP to CBP1.
+    LoadCbp1 = 3,
+    /// Load the CLUT buffer if CBP differs from CBP0, and copy CBP to CBP0.
+    CompareCbp0 = 4,
+    /// Load the CLUT buffer if CBP differs from CBP1, and copy CBP to CBP1.
+    CompareCbp1 = 5,
+}
+
+/// The position of palette entry `index` in a 256-colour CSM1 CLUT.
+///
+/// Within each group of 32 entries, entries 8-15 and 16-23 trade places, which swaps bits 3 and 4
+/// of the index. Applying this twice gives back the original index.
+pub fn csm1_index(index: usize) -> usize {
+    (index & !0x18) | ((index & 0x08) << 1) | ((index & 0x10) >> 1)
+}
+
+/// A CLUT in its local memory layout.
+pub struct Clut {
+    data: Aligned<A16, [u128; 64]>,
+    entries: usize,
+    cpsm: Psm,
+    csm: Csm,
+}
+
+impl Clut {
+    /// Lay out a palette of 16 or 256 32-bit colours.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the palette does not hold 16 or 256 colours, or `csm` is `Csm2`, which only
+    /// supports 16-bit colours.
+    pub fn ct32(colours: &[u32], csm: Csm) -> Self {
+        assert!(csm == Csm::Csm1, "CSM2 CLUTs must be PSMCT16");
+        Clut::new(colours, Psm::PSMCT32, csm)
+    }
+
+    /// Lay out a palette of 16 or 256 16-bit colours.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the palette does not hold 16 or 256 colours.
+    pub fn ct16(colours: &[u16], csm: Csm) -> Self {
+        Clut::new(colours, Psm::PSMCT16, csm)
+    }
+
+    fn new<C: Copy + Into<u32>>(colours: &[C], cpsm: Psm, csm: Csm) -> Self {
+        let entries = colours.len();
+        assert!(
+            entries == 16 || entries == 256,
+            "A CLUT holds 16 or 256 colours, not {}",
+            entries
+        );
+
+        let bits = cpsm.bits_per_pixel() as usize;
+        let per_qword = 128 / bits;
+        let mut data = Aligned([0u128; 64]);
+        for (index, &colour) in colours.iter().enumerate() {
+            let position = if csm == Csm::Csm1 && entries == 256 {
+                csm1_index(index)
+            } else {
+                index
+            };
+            data[position / per_qword] |=
+                (colour.into() as u128) << (bits * (position % per_qword));
+        }
+
+        Clut {
+            data,
+            entries,
+            cpsm,
+            csm,
+        }
+    }
+
+    /// The number of colours in the CLUT.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// The storage mode of the colours.
+    pub fn cpsm(&self) -> Psm {
+        self.cpsm
+    }
+
+    /// The CLUT storage mode.
+    pub fn csm(&self) -> Csm {
+        self.csm
+    }
+
+    /// The size in pixels of the CLUT image in local memory.
+    pub fn size(&self) -> (u16, u16) {
+        match (self.csm, self.entries) {
+            (Csm::Csm1, 16) => (8, 2),
+            (Csm::Csm1, _) => (16, 16),
+            (Csm::Csm2, entries) => (entries as u16, 1),
+        }
+    }
+
+    /// The CLUT data, in the order it is uploaded.
+    pub fn qwords(&self) -> &[u128] {
+        let len = self.entries * self.cpsm.bits_per_pixel() as usize / 128;
+        &self.data[..len]
+    }
+
+    /// The transfer which writes the CLUT to block `cbp`.
+    pub fn destination(&self, cbp: u16) -> Upload {
+        let (width, height) = self.size();
+        Upload::new(
+            cbp,
+            buffer_width(width as u32, self.cpsm) as u8,
+            self.cpsm,
+            Rect::new(0, 0, width, height),
+        )
+    }
+
+    /// Append the transfer which writes the CLUT to block `cbp`, copying its data into the packet.
+    pub fn packet(&self, packet: &mut GifPacket, cbp: u16, eop: bool) {
+        self.destination(cbp).packet(packet, self.qwords(), eop);
+    }
+
+    /// Upload the CLUT to block `cbp`, waiting for the transfer to finish.
+    pub fn upload(&mut self, gif: Gif, cbp: u16) -> Gif {
+        let dest = self.destination(cbp);
+        let len = dest.qwords();
+        let data: &mut [u128] = &mut self.data[..];
+        let data = unsafe {
+            // The start of the data is the start of the aligned buffer.
+            &mut *(core::ptr::slice_from_raw_parts_mut(data.as_mut_ptr(), len)
+                as *mut Aligned<A16, [u128]>)
+        };
+        upload(gif, &dest, data)
+    }
+
+    /// Fill in the CLUT fields of `tex0` for a CLUT stored at block `cbp`.
+    pub fn tex0(&self, tex0: TEX0, cbp: u16, load: ClutLoad) -> TEX0 {
+        tex0.with_cbp(cbp)
+            .with_cpsm(self.cpsm as u8)
+            .with_csm(self.csm == Csm::Csm2)
+            .with_csa(0)
+            .with_cld(load as u8)
+    }
+
+    /// The TEXCLUT value locating a CSM2 CLUT at the start of its buffer.
+    pub fn texclut(&self) -> TEXCLUT {
+        let (width, _) = self.size();
+        TEXCLUT::new()
+            .with_cbw(buffer_width(width as u32, self.cpsm) as u8)
+            .with_cou(0)
+            .with_cov(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csm1_swaps_middle_entries() {
+        let swizzled: [usize; 32] = core::array::from_fn(csm1_index);
+        assert_eq!(&swizzled[..8], &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(&swizzled[8..16], &[16, 17, 18, 19, 20, 21, 22, 23]);
+        assert_eq!(&swizzled[16..24], &[8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(&swizzled[24..], &[24, 25, 26, 27, 28, 29, 30, 31]);
+        assert_eq!(csm1_index(232), 240);
+        assert!((0..256).all(|i| csm1_index(csm1_index(i)) == i));
+    }
+
+    #[test]
+    fn ct32_csm1_layout() {
+        let colours: [u32; 256] = core::array::from_fn(|i| i as u32);
+        let clut = Clut::ct32(&colours, Csm::Csm1);
+        let data = clut.qwords();
+
+        assert_eq!(data.len(), 64);
+        assert_eq!(data[0], 0x0000_0003_0000_0002_0000_0001_0000_0000);
+        // Entries 16-19 are stored where 8-11 would be.
+        assert_eq!(data[2], 0x0000_0013_0000_0012_0000_0011_0000_0010);
+        assert_eq!(data[4], 0x0000_000B_0000_000A_0000_0009_0000_0008);
+        assert_eq!(clut.size(), (16, 16));
+    }
+
+    #[test]
+    fn small_cluts_are_not_swizzled() {
+        let colours: [u16; 16] = core::array::from_fn(|i| i as u16);
+        let clut = Clut::ct16(&colours, Csm::Csm1);
+
+        assert_eq!(clut.qwords().len(), 2);
+        assert_eq!(clut.qwords()[1], 0x000F_000E_000D_000C_000B_000A_0009_0008);
+        assert_eq!(clut.size(), (8, 2));
+    }
+
+    #[test]
+    fn csm2_is_linear() {
+        let colours: [u16; 256] = core::array::from_fn(|i| i as u16);
+        let clut = Clut::ct16(&colours, Csm::Csm2);
+
+        assert_eq!(clut.qwords()[1], 0x000F_000E_000D_000C_000B_000A_0009_0008);
+        assert_eq!(clut.size(), (256, 1));
+        assert_eq!(u64::from(clut.texclut()), 4);
+    }
+
+    #[test]
+    fn tex0_fields() {
+        let clut = Clut::ct16(&[0; 16], Csm::Csm2);
+        let tex0 = clut.tex0(TEX0::new(), 0x3FF0, ClutLoad::LoadCbp0);
+
+        assert_eq!(tex0.cbp(), 0x3FF0);
+        assert_eq!(tex0.cpsm(), Psm::PSMCT16 as u8);
+        assert!(tex0.csm());
+        assert_eq!(tex0.cld(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn csm2_rejects_ct32() {
+        Clut::ct32(&[0; 16], Csm::Csm2);
+    }
+}
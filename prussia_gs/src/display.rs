@@ -0,0 +1,420 @@
+//! Display output setup.
+//!
+//! The PCRTC scans the framebuffer out through two read circuits. Each circuit is told where the
+//! framebuffer is (DISPFB) and where on screen to put it (DISPLAY). The DISPLAY registers work in
+//! video clock (VCK) units horizontally and raster lines vertically, so a framebuffer narrower than
+//! the mode's visible area has to be magnified (MAGH) to fill it.
+//!
+//! `Display` describes an output configuration and computes all of these register values, and
+//! `Display::apply` sets the video mode through the BIOS and then programs the registers.
+//!
+//! # Examples
+//!
+//! ```
+//! use prussia_gs::display::Display;
+//!
+//! let regs = Display::ntsc().registers(0);
+//! assert_eq!(regs.display1.magh(), 3);
+//! ```
+
+use crate::privileged::{DISPFB1, DISPFB2, DISPLAY1, DISPLAY2, PMODE, SMODE2};
+use crate::registers::Psm;
+
+/// Video output standards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoMode {
+    /// NTSC, as used in North America and Japan.
+    Ntsc,
+    /// PAL, as used in most of Europe.
+    Pal,
+    /// 480-line progressive, over component video.
+    Dtv480p,
+    /// 720-line progressive, over component video.
+    Dtv720p,
+    /// 1080-line interlaced, over component video.
+    Dtv1080i,
+}
+
+/// How frames are scanned out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scan {
+    /// Every line of every frame. NTSC and PAL show half as many lines in this mode.
+    Progressive,
+    /// Interlaced, with each field reading every other line of the framebuffer.
+    Field,
+    /// Interlaced, with each field reading every line of the framebuffer.
+    Frame,
+}
+
+/// The visible area of a video mode.
+struct Timing {
+    /// Horizontal start, in VCK units.
+    dx: u16,
+    /// Vertical start, in raster lines.
+    dy: u16,
+    /// Width, in VCK units.
+    width: u16,
+    /// Height, in raster lines.
+    height: u16,
+}
+
+impl VideoMode {
+    fn timing(self, scan: Scan) -> Timing {
+        let timing = match self {
+            VideoMode::Ntsc => Timing {
+                dx: 632,
+                dy: 50,
+                width: 2560,
+                height: 448,
+            },
+            VideoMode::Pal => Timing {
+                dx: 652,
+                dy: 72,
+                width: 2560,
+                height: 512,
+            },
+            VideoMode::Dtv480p => Timing {
+                dx: 232,
+                dy: 35,
+                width: 1440,
+                height: 480,
+            },
+            VideoMode::Dtv720p => Timing {
+                dx: 302,
+                dy: 24,
+                width: 1280,
+                height: 720,
+            },
+            VideoMode::Dtv1080i => Timing {
+                dx: 238,
+                dy: 40,
+                width: 1920,
+                height: 1080,
+            },
+        };
+
+        match (self, scan) {
+            // Without interlacing, NTSC and PAL only scan out one field's worth of lines.
+            (VideoMode::Ntsc, Scan::Progressive) | (VideoMode::Pal, Scan::Progressive) => Timing {
+                dy: timing.dy / 2,
+                height: timing.height / 2,
+                ..timing
+            },
+            (VideoMode::Dtv480p, Scan::Progressive)
+            | (VideoMode::Dtv720p, Scan::Progressive)
+            | (VideoMode::Ntsc, _)
+            | (VideoMode::Pal, _) => timing,
+            (VideoMode::Dtv1080i, Scan::Field) | (VideoMode::Dtv1080i, Scan::Frame) => timing,
+            (mode, scan) => panic!("{:?} cannot be scanned out as {:?}", mode, scan),
+        }
+    }
+}
+
+/// Register values for both read circuits, as computed by `Display::registers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayRegisters {
+    /// PCRTC mode.
+    pub pmode: PMODE,
+    /// Interlacing mode.
+    pub smode2: SMODE2,
+    /// Framebuffer for read circuit 1.
+    pub dispfb1: DISPFB1,
+    /// Framebuffer for read circuit 2.
+    pub dispfb2: DISPFB2,
+    /// Screen area for read circuit 1.
+    pub display1: DISPLAY1,
+    /// Screen area for read circuit 2.
+    pub display2: DISPLAY2,
+}
+
+impl DisplayRegisters {
+    /// Write these values to the privileged registers.
+    pub fn store(&self) {
+        self.pmode.store();
+        self.smode2.store();
+        self.dispfb1.store();
+        self.dispfb2.store();
+        self.display1.store();
+        self.display2.store();
+    }
+}
+
+/// A display output configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Display {
+    /// The video standard to output.
+    pub mode: VideoMode,
+    /// How frames are scanned out.
+    pub scan: Scan,
+    /// The width of the framebuffer, in pixels.
+    pub width: u16,
+    /// The height of the framebuffer, in pixels.
+    pub height: u16,
+    /// The pixel format of the framebuffer.
+    pub psm: Psm,
+    /// Whether to blend adjacent lines together, reducing flicker on interlaced displays.
+    pub flicker_filter: bool,
+}
+
+impl Display {
+    /// Create a display configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` or `height` is zero or more than 2048, if `psm` is not a colour format, or
+    /// if `mode` does not support `scan`.
+    pub fn new(mode: VideoMode, scan: Scan, width: u16, height: u16, psm: Psm) -> Self {
+        assert!(
+            (1..=2048).contains(&width) && (1..=2048).contains(&height),
+            "A {}x{} framebuffer cannot be displayed",
+            width,
+            height
+        );
+        assert!(
+            matches!(
+                psm,
+                Psm::PSMCT32 | Psm::PSMCT24 | Psm::PSMCT16 | Psm::PSMCT16S
+            ),
+            "The PCRTC cannot display {:?}",
+            psm
+        );
+        // Check the combination of mode and scan is valid.
+        mode.timing(scan);
+
+        Display {
+            mode,
+            scan,
+            width,
+            height,
+            psm,
+            flicker_filter: false,
+        }
+    }
+
+    /// 640x448 interlaced NTSC.
+    pub fn ntsc() -> Self {
+        Display::new(VideoMode::Ntsc, Scan::Field, 640, 448, Psm::PSMCT32)
+    }
+
+    /// 640x512 interlaced PAL.
+    pub fn pal() -> Self {
+        Display::new(VideoMode::Pal, Scan::Field, 640, 512, Psm::PSMCT32)
+    }
+
+    /// 640x480 progressive.
+    pub fn dtv480p() -> Self {
+        Display::new(
+            VideoMode::Dtv480p,
+            Scan::Progressive,
+            640,
+            480,
+            Psm::PSMCT32,
+        )
+    }
+
+    /// 1280x720 progressive, in 16-bit colour to leave room in GS memory.
+    pub fn dtv720p() -> Self {
+        Display::new(
+            VideoMode::Dtv720p,
+            Scan::Progressive,
+            1280,
+            720,
+            Psm::PSMCT16,
+        )
+    }
+
+    /// 1920x1080 interlaced, from a 1920x540 16-bit framebuffer shown in both fields.
+    pub fn dtv1080i() -> Self {
+        Display::new(VideoMode::Dtv1080i, Scan::Frame, 1920, 540, Psm::PSMCT16)
+    }
+
+    /// Enable or disable the flicker filter.
+    pub fn with_flicker_filter(mut self, flicker_filter: bool) -> Self {
+        self.flicker_filter = flicker_filter;
+        self
+    }
+
+    /// Compute the register values to display the framebuffer at page `fbp`.
+    pub fn registers(&self, fbp: u16) -> DisplayRegisters {
+        let timing = self.mode.timing(self.scan);
+
+        // Magnify horizontally to fill as much of the visible area as possible, and centre what
+        // is left over.
+        let magh = (timing.width / self.width).clamp(1, 16) - 1;
+        let dw = (self.width * (magh + 1)).min(timing.width);
+        let dx = timing.dx + (timing.width - dw) / 2;
+
+        // In FRAME mode both fields show every line of the framebuffer, so it covers twice as
+        // many raster lines.
+        let lines = match self.scan {
+            Scan::Frame => self.height * 2,
+            Scan::Progressive | Scan::Field => self.height,
+        };
+        let magv = (timing.height / lines).clamp(1, 4) - 1;
+        let dh = (lines * (magv + 1)).min(timing.height);
+        let dy = timing.dy + (timing.height - dh) / 2;
+
+        let fbw = self.width.div_ceil(64);
+        let psm = self.psm as u16;
+
+        // The flicker filter blends each line with the one below it, by having circuit 1 read
+        // one line further down than circuit 2.
+        let (alp, dby1) = if self.flicker_filter {
+            (0x80, 1)
+        } else {
+            (0xFF, 0)
+        };
+
+        DisplayRegisters {
+            pmode: PMODE::new()
+                .with_en1(true)
+                .with_en2(self.flicker_filter)
+                .with_crtmd(1)
+                .with_mmod(true)
+                .with_alp(alp),
+            smode2: SMODE2::new()
+                .with_int(self.scan != Scan::Progressive)
+                .with_ffmd(self.scan == Scan::Frame),
+            dispfb1: DISPFB1::new()
+                .with_fbp(fbp)
+                .with_fbw(fbw)
+                .with_psm(psm)
+                .with_dby(dby1),
+            dispfb2: DISPFB2::new().with_fbp(fbp).with_fbw(fbw).with_psm(psm),
+            display1: DISPLAY1::new()
+                .with_dx(dx)
+                .with_dy(dy)
+                .with_magh(magh)
+                .with_magv(magv)
+                .with_dw(dw - 1)
+                .with_dh(dh - 1),
+            display2: DISPLAY2::new()
+                .with_dx(dx)
+                .with_dy(dy)
+                .with_magh(magh)
+                .with_magv(magv)
+                .with_dw(dw - 1)
+                .with_dh(dh - 1),
+        }
+    }
+
+    /// Set the video mode through the BIOS, then display the framebuffer at page `fbp`.
+    #[cfg(target_arch = "mips")]
+    pub fn apply(&self, fbp: u16) {
+        use prussia_bios::{FieldFrameMode, Interlacing, VideoMode as BiosMode};
+
+        let imode = match self.scan {
+            Scan::Progressive => Interlacing::Noninterlaced,
+            Scan::Field | Scan::Frame => Interlacing::Interlaced,
+        };
+        let vmode = match self.mode {
+            VideoMode::Ntsc => BiosMode::NTSC,
+            VideoMode::Pal => BiosMode::PAL,
+            VideoMode::Dtv480p => BiosMode::DTV480P,
+            VideoMode::Dtv720p => BiosMode::DTV720P,
+            VideoMode::Dtv1080i => BiosMode::DTV1080I,
+        };
+        let ffmode = match self.scan {
+            Scan::Frame => FieldFrameMode::Frame,
+            Scan::Progressive | Scan::Field => FieldFrameMode::Field,
+        };
+
+        prussia_bios::set_gs_crt(imode, vmode, ffmode);
+        self.registers(fbp).store();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntsc_preset() {
+        let regs = Display::ntsc().registers(0);
+
+        assert_eq!(regs.smode2, SMODE2::new().with_int(true));
+        assert_eq!(regs.dispfb1, DISPFB1::new().with_fbw(10));
+        assert_eq!(
+            regs.display1,
+            DISPLAY1::new()
+                .with_dx(632)
+                .with_dy(50)
+                .with_magh(3)
+                .with_dw(2559)
+                .with_dh(447)
+        );
+        assert_eq!(u64::from(regs.display1), u64::from(regs.display2));
+        assert!(regs.pmode.en1() && !regs.pmode.en2());
+    }
+
+    #[test]
+    fn pal_preset() {
+        let regs = Display::pal().registers(0x96);
+
+        assert_eq!(regs.dispfb2.fbp(), 0x96);
+        assert_eq!(regs.display2.dx(), 652);
+        assert_eq!(regs.display2.dy(), 72);
+        assert_eq!(regs.display2.dh(), 511);
+    }
+
+    #[test]
+    fn dtv_presets() {
+        let regs = Display::dtv480p().registers(0);
+        assert!(!regs.smode2.int());
+        assert_eq!(regs.display1.magh(), 1);
+        assert_eq!(regs.display1.dw(), 1279);
+        assert_eq!(regs.display1.dx(), 312);
+
+        let regs = Display::dtv1080i().registers(0);
+        assert!(regs.smode2.int() && regs.smode2.ffmd());
+        assert_eq!(regs.dispfb1.fbw(), 30);
+        assert_eq!(regs.dispfb1.psm(), Psm::PSMCT16 as u16);
+        assert_eq!(regs.display1.magh(), 0);
+        assert_eq!(regs.display1.dh(), 1079);
+    }
+
+    #[test]
+    fn small_framebuffers_are_magnified() {
+        let field = Display::new(VideoMode::Ntsc, Scan::Field, 320, 224, Psm::PSMCT16);
+        let regs = field.registers(0);
+        assert_eq!(regs.display1.magh(), 7);
+        assert_eq!(regs.display1.magv(), 1);
+        assert_eq!(regs.display1.dh(), 447);
+
+        let frame = Display::new(VideoMode::Ntsc, Scan::Frame, 320, 224, Psm::PSMCT16);
+        assert_eq!(frame.registers(0).display1.magv(), 0);
+
+        let progressive = Display::new(VideoMode::Ntsc, Scan::Progressive, 640, 224, Psm::PSMCT32);
+        let regs = progressive.registers(0);
+        assert_eq!(regs.display1.dy(), 25);
+        assert_eq!(regs.display1.dh(), 223);
+    }
+
+    #[test]
+    fn flicker_filter_uses_both_circuits() {
+        let regs = Display::ntsc().with_flicker_filter(true).registers(0);
+
+        assert!(regs.pmode.en1() && regs.pmode.en2());
+        assert_eq!(regs.pmode.alp(), 0x80);
+        assert_eq!(regs.dispfb1.dby(), 1);
+        assert_eq!(regs.dispfb2.dby(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn interlaced_480p_is_rejected() {
+        Display::new(VideoMode::Dtv480p, Scan::Field, 640, 480, Psm::PSMCT32);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_framebuffer_is_rejected() {
+        Display::new(VideoMode::Ntsc, Scan::Field, 640, 0, Psm::PSMCT32);
+    }
+
+    #[test]
+    #[should_panic]
+    fn oversized_framebuffer_is_rejected() {
+        Display::new(VideoMode::Ntsc, Scan::Frame, 640, 40_000, Psm::PSMCT32);
+    }
+}
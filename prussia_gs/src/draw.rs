@@ -0,0 +1,432 @@
+//! Immediate-mode drawing of untextured primitives.
+//!
+//! `Canvas` batches points, lines, triangles, strips, fans and sprites into a `GifPacket`, and sends
+//! the packet to an `Output` whenever it fills up, or when `Canvas::flush` is called at the end of
+//! the frame.
+//!
+//! Coordinates are in pixels, with (0, 0) at the top-left of the framebuffer. The GS works in 12.4
+//! fixed point relative to the XYOFFSET register, so each vertex is moved by the canvas offset and
+//! converted; `Canvas::setup` writes the matching XYOFFSET and a scissor for the framebuffer.
+//! The GS coordinate space is 4096 pixels across, so coordinates must lie between minus the offset
+//! and 4096 minus the offset; vertices outside that range are clamped to its edges.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_gs::draw::{Canvas, Colour, GifOutput};
+//! use prussia_gs::registers::Context;
+//!
+//! fn draw_menu(gif: prussia_dma::Gif) -> prussia_dma::Gif {
+//!     static mut BUFFER: Aligned<A16, [u128; 1024]> = Aligned([0; 1024]);
+//!
+//!     let mut canvas = Canvas::new(unsafe { &mut BUFFER }, GifOutput::new(gif));
+//!     canvas.setup(Context::One, 640, 448);
+//!     canvas.rect(0.0, 0.0, 640.0, 448.0, Colour::rgb(0, 0, 64));
+//!     canvas.rect(200.0, 150.0, 240.0, 32.0, Colour::rgb(255, 255, 255));
+//!     canvas.line(200.0, 190.0, 440.0, 190.0, Colour::rgb(255, 0, 0));
+//!     canvas.flush();
+//!     canvas.into_output().into_inner()
+//! }
+//! ```
+
+use aligned::{Aligned, A16};
+use prussia_dma::Gif;
+
+use crate::gif::{Descriptor, GifPacket, GifTag, MAX_NLOOP};
+use crate::registers::{Context, Primitive, PRIM, PRMODECONT, SCISSOR, XYOFFSET};
+use crate::transfer::send;
+
+/// Somewhere to send finished packets.
+pub trait Output {
+    /// Send `packet`, returning once its buffer can be reused.
+    fn send(&mut self, packet: &mut Aligned<A16, [u128]>);
+}
+
+/// Sends packets over the GIF DMA channel.
+pub struct GifOutput {
+    gif: Option<Gif>,
+}
+
+impl GifOutput {
+    /// Send packets through `gif`.
+    pub fn new(gif: Gif) -> Self {
+        GifOutput { gif: Some(gif) }
+    }
+
+    /// Give back the GIF channel.
+    pub fn into_inner(self) -> Gif {
+        self.gif.unwrap()
+    }
+}
+
+impl Output for GifOutput {
+    fn send(&mut self, packet: &mut Aligned<A16, [u128]>) {
+        let gif = self.gif.take().unwrap();
+        self.gif = Some(send(gif, packet));
+    }
+}
+
+/// A vertex colour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Colour {
+    /// Red intensity.
+    pub r: u8,
+    /// Green intensity.
+    pub g: u8,
+    /// Blue intensity.
+    pub b: u8,
+    /// Alpha, where 0x80 is fully opaque.
+    pub a: u8,
+}
+
+impl Colour {
+    /// A colour with the given alpha, where 0x80 is fully opaque.
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Colour { r, g, b, a }
+    }
+
+    /// An opaque colour.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Colour::new(r, g, b, 0x80)
+    }
+
+    /// The colour in the PACKED format of RGBAQ.
+    fn packed(self) -> u128 {
+        (self.r as u128) | (self.g as u128) << 32 | (self.b as u128) << 64 | (self.a as u128) << 96
+    }
+}
+
+/// A vertex with its own colour, for Gouraud shaded primitives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    /// The horizontal position, in pixels.
+    pub x: f32,
+    /// The vertical position, in pixels.
+    pub y: f32,
+    /// The colour at this vertex.
+    pub colour: Colour,
+}
+
+impl Vertex {
+    /// Create a vertex at (`x`, `y`).
+    pub const fn new(x: f32, y: f32, colour: Colour) -> Self {
+        Vertex { x, y, colour }
+    }
+}
+
+/// A batch of primitives being built for the GS.
+pub struct Canvas<'a, O: Output> {
+    packet: GifPacket<'a>,
+    output: O,
+    context: Context,
+    offset: (u16, u16),
+    blend: bool,
+}
+
+impl<'a, O: Output> Canvas<'a, O> {
+    /// Create a canvas which builds packets in `buffer` and sends them to `output`.
+    ///
+    /// The default offset places pixel (0, 0) at 1024 in the GS coordinate space, leaving room
+    /// either side for primitives which are partly off the screen.
+    pub fn new(buffer: &'a mut Aligned<A16, [u128]>, output: O) -> Self {
+        Canvas {
+            packet: GifPacket::new(buffer),
+            output,
+            context: Context::One,
+            offset: (1024, 1024),
+            blend: false,
+        }
+    }
+
+    /// Use a different offset between pixel coordinates and the GS coordinate space.
+    pub fn with_offset(mut self, x: u16, y: u16) -> Self {
+        self.offset = (x, y);
+        self
+    }
+
+    /// Give back the output.
+    pub fn into_output(self) -> O {
+        self.output
+    }
+
+    /// The XYOFFSET value matching the canvas offset.
+    pub fn xyoffset(&self) -> XYOFFSET {
+        XYOFFSET::new()
+            .with_ofx(self.offset.0 << 4)
+            .with_ofy(self.offset.1 << 4)
+    }
+
+    /// Draw with `context` from now on, and write its XYOFFSET and a scissor covering `width` by
+    /// `height` pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` or `height` is zero.
+    pub fn setup(&mut self, context: Context, width: u16, height: u16) {
+        assert!(
+            width > 0 && height > 0,
+            "A {}x{} framebuffer is empty",
+            width,
+            height
+        );
+        self.context = context;
+        let xyoffset = self.xyoffset();
+        let scissor = SCISSOR::new().with_scax1(width - 1).with_scay1(height - 1);
+
+        self.reserve(4);
+        self.packet
+            .tag(GifTag::packed(3, &[Descriptor::AD]).with_eop(true))
+            .context_register(context, xyoffset)
+            .context_register(context, scissor)
+            .register(PRMODECONT::new().with_ac(true));
+    }
+
+    /// Alpha blend the primitives drawn from now on, using the context's ALPHA setting.
+    pub fn set_blend(&mut self, blend: bool) {
+        self.blend = blend;
+    }
+
+    /// Draw a single point.
+    pub fn point(&mut self, x: f32, y: f32, colour: Colour) {
+        self.flat(Primitive::Point, &[(x, y)], colour);
+    }
+
+    /// Draw a line from (`x0`, `y0`) to (`x1`, `y1`).
+    pub fn line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, colour: Colour) {
+        self.flat(Primitive::Line, &[(x0, y0), (x1, y1)], colour);
+    }
+
+    /// Draw a filled triangle.
+    pub fn triangle(&mut self, points: [(f32, f32); 3], colour: Colour) {
+        self.flat(Primitive::Triangle, &points, colour);
+    }
+
+    /// Draw a filled rectangle with its top-left corner at (`x`, `y`).
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, colour: Colour) {
+        self.flat(
+            Primitive::Sprite,
+            &[(x, y), (x + width, y + height)],
+            colour,
+        );
+    }
+
+    /// Draw connected lines through `points`.
+    pub fn line_strip(&mut self, points: &[(f32, f32)], colour: Colour) {
+        self.flat(Primitive::LineStrip, points, colour);
+    }
+
+    /// Draw a strip of triangles, each made of a point and the two before it.
+    pub fn triangle_strip(&mut self, points: &[(f32, f32)], colour: Colour) {
+        self.flat(Primitive::TriangleStrip, points, colour);
+    }
+
+    /// Draw a fan of triangles, each made of a point, the one before it and the first point.
+    pub fn triangle_fan(&mut self, points: &[(f32, f32)], colour: Colour) {
+        self.flat(Primitive::TriangleFan, points, colour);
+    }
+
+    /// Draw a Gouraud shaded primitive, blending the colours between its vertices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vertices do not fit in an empty packet buffer.
+    pub fn shaded(&mut self, primitive: Primitive, vertices: &[Vertex]) {
+        if vertices.is_empty() {
+            return;
+        }
+        self.begin(primitive, true, vertices.len());
+        for vertex in vertices {
+            let xyz = self.xyz(vertex.x, vertex.y);
+            self.packet.qword(vertex.colour.packed()).qword(xyz);
+        }
+    }
+
+    /// Send everything drawn so far.
+    pub fn flush(&mut self) {
+        if !self.packet.is_empty() {
+            self.output.send(self.packet.contents());
+            self.packet.clear();
+        }
+    }
+
+    fn flat(&mut self, primitive: Primitive, points: &[(f32, f32)], colour: Colour) {
+        if points.is_empty() {
+            return;
+        }
+        let colour = colour.packed();
+        self.begin(primitive, false, points.len());
+        for &(x, y) in points {
+            let xyz = self.xyz(x, y);
+            self.packet.qword(colour).qword(xyz);
+        }
+    }
+
+    /// Start a primitive of `vertices` vertices, flushing first if it will not fit.
+    fn begin(&mut self, primitive: Primitive, gouraud: bool, vertices: usize) {
+        assert!(
+            vertices <= MAX_NLOOP as usize,
+            "Too many vertices in one primitive"
+        );
+        self.reserve(1 + 2 * vertices);
+
+        let prim = PRIM::new()
+            .with_prim(primitive as u8)
+            .with_iip(gouraud)
+            .with_abe(self.blend)
+            .with_ctxt(self.context == Context::Two);
+        // Each primitive is a complete GIF packet, so a flush never splits one.
+        let tag = GifTag::packed(vertices as u16, &[Descriptor::RGBAQ, Descriptor::XYZ2])
+            .with_prim(u64::from(prim) as u16)
+            .with_eop(true);
+        self.packet.tag(tag);
+    }
+
+    /// Make room for `qwords` more quadwords, flushing if necessary.
+    fn reserve(&mut self, qwords: usize) {
+        if self.packet.remaining() < qwords {
+            self.flush();
+        }
+        assert!(
+            self.packet.remaining() >= qwords,
+            "Primitive does not fit in the packet buffer"
+        );
+    }
+
+    /// The PACKED XYZ2 value for pixel (`x`, `y`), clamped to the GS coordinate space.
+    fn xyz(&self, x: f32, y: f32) -> u128 {
+        let fixed = |pixel: f32, offset: u16| {
+            ((pixel + offset as f32) * 16.0).clamp(0.0, u16::MAX as f32) as u16
+        };
+        let x = fixed(x, self.offset.0);
+        let y = fixed(y, self.offset.1);
+        (x as u128) | (y as u128) << 32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the first few packets sent to it.
+    struct Recorder {
+        packets: [[u128; 16]; 4],
+        lens: [usize; 4],
+        count: usize,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Recorder {
+                packets: [[0; 16]; 4],
+                lens: [0; 4],
+                count: 0,
+            }
+        }
+
+        fn packet(&self, i: usize) -> &[u128] {
+            &self.packets[i][..self.lens[i]]
+        }
+    }
+
+    impl Output for Recorder {
+        fn send(&mut self, packet: &mut Aligned<A16, [u128]>) {
+            let packet: &[u128] = packet;
+            self.packets[self.count][..packet.len()].copy_from_slice(packet);
+            self.lens[self.count] = packet.len();
+            self.count += 1;
+        }
+    }
+
+    #[test]
+    fn sprite_layout() {
+        let mut buffer = Aligned([0u128; 16]);
+        let mut canvas = Canvas::new(&mut buffer, Recorder::new());
+        canvas.rect(10.0, 20.0, 30.5, 40.0, Colour::rgb(1, 2, 3));
+        canvas.flush();
+        let recorder = canvas.into_output();
+
+        assert_eq!(recorder.count, 1);
+        let packet = recorder.packet(0);
+        assert_eq!(packet.len(), 5);
+        // NLOOP 2, EOP, PRE, PRIM sprite, PACKED, RGBAQ + XYZ2.
+        assert_eq!(packet[0], 0x0000_0000_0000_0051_2003_4000_0000_8002);
+        assert_eq!(packet[1], 0x0000_0080_0000_0003_0000_0002_0000_0001);
+        assert_eq!(packet[2], ((1044 * 16) << 32) | (1034 * 16));
+        assert_eq!(packet[4], ((1084 * 16) << 32) | (1064 * 16 + 8));
+    }
+
+    #[test]
+    fn setup_matches_offset() {
+        let mut buffer = Aligned([0u128; 8]);
+        let mut canvas =
+            Canvas::new(&mut buffer, Recorder::new()).with_offset(2048 - 320, 2048 - 224);
+        canvas.setup(Context::Two, 640, 448);
+        canvas.point(0.0, 0.0, Colour::rgb(0, 0, 0));
+        canvas.flush();
+        let recorder = canvas.into_output();
+        let packet = recorder.packet(0);
+
+        assert_eq!(packet[1], (0x19 << 64) | (1824 * 16) << 32 | (1728 * 16));
+        assert_eq!(packet[2], (0x41 << 64) | (447 << 48) | (639 << 16));
+        assert_eq!(packet[3], (0x1A << 64) | 1);
+        // The point is drawn with context 2, at the offset.
+        assert_eq!((packet[4] >> 47) & 0x7FF, 0x200);
+        assert_eq!(packet[6], (1824 * 16) << 32 | (1728 * 16));
+    }
+
+    #[test]
+    fn coordinates_clamp_to_gs_space() {
+        let mut buffer = Aligned([0u128; 1]);
+        let canvas = Canvas::new(&mut buffer, Recorder::new());
+
+        assert_eq!(canvas.xyz(-1024.0, -2000.0), 0);
+        assert_eq!(canvas.xyz(3072.0, 5000.0), 0xFFFF << 32 | 0xFFFF);
+    }
+
+    #[test]
+    #[should_panic(expected = "framebuffer is empty")]
+    fn setup_rejects_empty_framebuffer() {
+        let mut buffer = Aligned([0u128; 8]);
+        Canvas::new(&mut buffer, Recorder::new()).setup(Context::One, 0, 448);
+    }
+
+    #[test]
+    fn flushes_when_full() {
+        let mut buffer = Aligned([0u128; 8]);
+        let mut canvas = Canvas::new(&mut buffer, Recorder::new());
+        for _ in 0..3 {
+            canvas.triangle([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], Colour::rgb(9, 9, 9));
+        }
+        canvas.flush();
+        let recorder = canvas.into_output();
+
+        assert_eq!(recorder.count, 3);
+        assert_eq!(recorder.lens, [7, 7, 7, 0]);
+    }
+
+    #[test]
+    fn shaded_strip() {
+        let mut buffer = Aligned([0u128; 16]);
+        let mut canvas = Canvas::new(&mut buffer, Recorder::new());
+        let red = Colour::rgb(255, 0, 0);
+        let blue = Colour::rgb(0, 0, 255);
+        canvas.shaded(
+            Primitive::TriangleStrip,
+            &[
+                Vertex::new(0.0, 0.0, red),
+                Vertex::new(8.0, 0.0, blue),
+                Vertex::new(0.0, 8.0, red),
+                Vertex::new(8.0, 8.0, blue),
+            ],
+        );
+        canvas.flush();
+        let recorder = canvas.into_output();
+        let packet = recorder.packet(0);
+
+        assert_eq!(packet.len(), 9);
+        // PRIM is a triangle strip with Gouraud shading.
+        assert_eq!((packet[0] >> 47) & 0x7FF, 0x0C);
+        assert_eq!(packet[3], blue.packed());
+    }
+}
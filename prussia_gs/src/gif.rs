@@ -0,0 +1,507 @@
+//! GS Interface (GIF) packet construction.
+//!
+//! Everything drawn by the GS arrives through the GIF as a stream of 128-bit quadwords. Each run of
+//! data is preceded by a GIFtag, which says how many times it loops (NLOOP), which registers the
+//! data is destined for (NREG/REGS) and how that data is laid out (FLG):
+//!
+//! - PACKED: every register gets a full quadword, in a register-specific format. The A+D
+//!   descriptor lets any register be written by putting its address alongside the data.
+//! - REGLIST: every register gets a doubleword, which is written to it unchanged.
+//! - IMAGE: the data is pixel data for a host-to-local transfer, and is written to HWREG.
+//!
+//! `GifPacket` lays these out in a 16-byte aligned buffer, which can then be handed to
+//! `prussia_dma::Transfer::from_mem` on the `Gif` channel.
+//!
+//! # Examples
+//!
+//! ```
+//! use aligned::{Aligned, A16};
+//! use prussia_gs::gif::GifPacket;
+//!
+//! fn clear_colour(gif: prussia_dma::Gif) -> prussia_dma::Gif {
+//!     static mut BUFFER: Aligned<A16, [u128; 4]> = Aligned([0; 4]);
+//!
+//!     let mut packet = GifPacket::new(unsafe { &mut BUFFER });
+//!     // Set RGBAQ to opaque red.
+//!     packet.ad_list(&[(0x01, 0x3F80_0000_8000_00FF)], true);
+//!
+//!     let (gif, _) = prussia_dma::Transfer::from_mem(gif, packet.finish()).wait();
+//!     gif
+//! }
+//! ```
+
+use core::ptr;
+
+use aligned::{Aligned, A16};
+use bitfield_struct::bitfield;
+
+use crate::registers::{Context, ContextRegister, Register};
+
+/// The largest loop count a single GIFtag can hold.
+pub const MAX_NLOOP: u16 = 0x7FFF;
+
+/// How the data following a GIFtag is laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// One quadword per register, in a register-specific format.
+    Packed = 0,
+    /// One doubleword per register, written to the register as-is.
+    Reglist = 1,
+    /// Pixel data for a host-to-local transfer.
+    Image = 2,
+}
+
+/// A register descriptor, selecting where one element of GIFtag data is written.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Descriptor {
+    /// Drawing primitive setting.
+    PRIM = 0x0,
+    /// Vertex colour.
+    RGBAQ = 0x1,
+    /// Vertex texture coordinates (floating point).
+    ST = 0x2,
+    /// Vertex texture coordinates (texel).
+    UV = 0x3,
+    /// Vertex position and fog coefficient, with drawing kick.
+    XYZF2 = 0x4,
+    /// Vertex position, with drawing kick.
+    XYZ2 = 0x5,
+    /// Texture information for context 1.
+    TEX0_1 = 0x6,
+    /// Texture information for context 2.
+    TEX0_2 = 0x7,
+    /// Texture wrap mode for context 1.
+    CLAMP_1 = 0x8,
+    /// Texture wrap mode for context 2.
+    CLAMP_2 = 0x9,
+    /// Vertex fog coefficient.
+    FOG = 0xA,
+    /// Vertex position and fog coefficient, without drawing kick.
+    XYZF3 = 0xC,
+    /// Vertex position, without drawing kick.
+    XYZ3 = 0xD,
+    /// Address + Data: the data quadword names the register it is written to.
+    AD = 0xE,
+    /// No output.
+    NOP = 0xF,
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// The lower doubleword of a GIFtag.
+pub struct GifTagHeader {
+    #[bits(15)]
+    pub nloop: u16,
+    pub eop: bool,
+    #[bits(30)]
+    __: u32,
+    pub pre: bool,
+    #[bits(11)]
+    pub prim: u16,
+    #[bits(2)]
+    pub flg: u8,
+    #[bits(4)]
+    pub nreg: u8,
+}
+
+/// A GIFtag: a header describing the data which follows, plus its register descriptors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GifTag {
+    /// The loop count, data format and optional PRIM value.
+    pub header: GifTagHeader,
+    /// Up to 16 four-bit register descriptors, the first in the lowest bits.
+    pub regs: u64,
+}
+
+impl GifTag {
+    /// Create a GIFtag for NLOOP repetitions of `descriptors` in PACKED format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nloop` exceeds `MAX_NLOOP` or `descriptors` does not hold between 1 and 16
+    /// entries.
+    pub fn packed(nloop: u16, descriptors: &[Descriptor]) -> Self {
+        GifTag::new(DataFormat::Packed, nloop, descriptors)
+    }
+
+    /// Create a GIFtag for NLOOP repetitions of `descriptors` in REGLIST format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nloop` exceeds `MAX_NLOOP` or `descriptors` does not hold between 1 and 16
+    /// entries.
+    pub fn reglist(nloop: u16, descriptors: &[Descriptor]) -> Self {
+        GifTag::new(DataFormat::Reglist, nloop, descriptors)
+    }
+
+    /// Create a GIFtag for `nloop` quadwords of IMAGE data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nloop` exceeds `MAX_NLOOP`.
+    pub fn image(nloop: u16) -> Self {
+        GifTag::new(DataFormat::Image, nloop, &[])
+    }
+
+    fn new(flg: DataFormat, nloop: u16, descriptors: &[Descriptor]) -> Self {
+        assert!(nloop <= MAX_NLOOP, "GIFtag NLOOP {} is too large", nloop);
+        if flg != DataFormat::Image {
+            assert!(
+                !descriptors.is_empty() && descriptors.len() <= 16,
+                "GIFtags hold between 1 and 16 register descriptors"
+            );
+        }
+
+        let regs = descriptors
+            .iter()
+            .enumerate()
+            .fold(0, |regs, (i, &desc)| regs | ((desc as u64) << (4 * i)));
+
+        let header = GifTagHeader::new()
+            .with_nloop(nloop)
+            .with_flg(flg as u8)
+            // An NREG of zero means sixteen registers.
+            .with_nreg((descriptors.len() & 0xF) as u8);
+
+        GifTag { header, regs }
+    }
+
+    /// Mark this GIFtag as the last one of the packet.
+    pub fn with_eop(mut self, eop: bool) -> Self {
+        self.header = self.header.with_eop(eop);
+        self
+    }
+
+    /// Write `prim` to the PRIM register before processing the data that follows.
+    pub fn with_prim(mut self, prim: u16) -> Self {
+        self.header = self.header.with_pre(true).with_prim(prim);
+        self
+    }
+
+    /// The number of registers each loop writes.
+    pub fn nreg(&self) -> usize {
+        match self.header.nreg() {
+            0 => 16,
+            n => n as usize,
+        }
+    }
+
+    /// The number of quadwords of data that follow this GIFtag.
+    pub fn data_qwords(&self) -> usize {
+        let nloop = self.header.nloop() as usize;
+        match self.header.flg() {
+            0 => nloop * self.nreg(),
+            1 => (nloop * self.nreg()).div_ceil(2),
+            _ => nloop,
+        }
+    }
+
+    /// Encode this GIFtag as a quadword.
+    pub fn to_qword(self) -> u128 {
+        u128::from(u64::from(self.header)) | (u128::from(self.regs) << 64)
+    }
+}
+
+/// A GIF packet under construction.
+///
+/// The packet is written into a caller-provided buffer, and `finish` returns the part of the
+/// buffer that was used, ready to be transferred. The buffer may be reborrowed with
+/// `prussia_rt::uncached::uncached`, so the packet is written straight to memory.
+pub struct GifPacket<'a> {
+    buffer: &'a mut Aligned<A16, [u128]>,
+    len: usize,
+}
+
+impl<'a> GifPacket<'a> {
+    /// Start a new, empty packet in `buffer`.
+    pub fn new(buffer: &'a mut Aligned<A16, [u128]>) -> Self {
+        GifPacket { buffer, len: 0 }
+    }
+
+    /// The number of quadwords written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of quadwords the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The number of quadwords that can still be written.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    /// Discard everything written so far.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// The quadwords written so far.
+    pub fn as_slice(&self) -> &[u128] {
+        let buffer: &[u128] = self.buffer;
+        &buffer[..self.len]
+    }
+
+    /// Append a raw quadword.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is full.
+    pub fn qword(&mut self, qword: u128) -> &mut Self {
+        assert!(self.len < self.capacity(), "GIF packet buffer is full");
+        let buffer: &mut [u128] = self.buffer;
+        buffer[self.len] = qword;
+        self.len += 1;
+        self
+    }
+
+    /// Append raw quadwords.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer does not have room for `qwords`.
+    pub fn qwords(&mut self, qwords: &[u128]) -> &mut Self {
+        assert!(
+            qwords.len() <= self.remaining(),
+            "GIF packet buffer is full"
+        );
+        let buffer: &mut [u128] = self.buffer;
+        buffer[self.len..self.len + qwords.len()].copy_from_slice(qwords);
+        self.len += qwords.len();
+        self
+    }
+
+    /// Append a GIFtag. The caller is responsible for appending the data it describes.
+    pub fn tag(&mut self, tag: GifTag) -> &mut Self {
+        self.qword(tag.to_qword())
+    }
+
+    /// Append a PACKED A+D element, writing `data` to the register at `addr`.
+    pub fn ad(&mut self, addr: u8, data: u64) -> &mut Self {
+        self.qword(u128::from(data) | (u128::from(addr) << 64))
+    }
+
+    /// Append a PACKED A+D element writing `reg`.
+    pub fn register<R: Register>(&mut self, reg: R) -> &mut Self {
+        self.ad(R::ADDRESS, reg.into())
+    }
+
+    /// Append a PACKED A+D element writing `reg` for the given drawing context.
+    pub fn context_register<R: ContextRegister>(&mut self, context: Context, reg: R) -> &mut Self {
+        self.ad(R::address(context), reg.into())
+    }
+
+    /// Append a PACKED GIFtag followed by one A+D element per `(address, data)` pair, splitting
+    /// the list at the NLOOP limit.
+    pub fn ad_list(&mut self, pairs: &[(u8, u64)], eop: bool) -> &mut Self {
+        let tags = pairs.len().div_ceil(MAX_NLOOP as usize).max(1);
+        for (i, chunk) in (0..tags).map(|i| nloop_chunk(pairs, i, 1)).enumerate() {
+            let tag = GifTag::packed(chunk.len() as u16, &[Descriptor::AD]);
+            self.tag(tag.with_eop(eop && i == tags - 1));
+            for &(addr, data) in chunk {
+                self.ad(addr, data);
+            }
+        }
+        self
+    }
+
+    /// Append a REGLIST GIFtag followed by `data`, which is written to `descriptors` in turn,
+    /// splitting it at the NLOOP limit.
+    ///
+    /// If the data of a GIFtag has an odd length, it is padded to a whole quadword as the GIF
+    /// requires.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `descriptors` is empty, or `data` does not hold a whole number of loops over
+    /// `descriptors`.
+    pub fn reglist(&mut self, descriptors: &[Descriptor], data: &[u64], eop: bool) -> &mut Self {
+        assert!(
+            !descriptors.is_empty(),
+            "REGLIST needs at least one register"
+        );
+        assert!(
+            data.len().is_multiple_of(descriptors.len()),
+            "REGLIST data must be a whole number of loops"
+        );
+
+        let nloop = data.len() / descriptors.len();
+        let tags = nloop.div_ceil(MAX_NLOOP as usize).max(1);
+        for i in 0..tags {
+            let chunk = nloop_chunk(data, i, descriptors.len());
+            let tag = GifTag::reglist((chunk.len() / descriptors.len()) as u16, descriptors);
+            self.tag(tag.with_eop(eop && i == tags - 1));
+            for pair in chunk.chunks(2) {
+                let low = u128::from(pair[0]);
+                let high = pair.get(1).map_or(0, |&high| u128::from(high) << 64);
+                self.qword(low | high);
+            }
+        }
+        self
+    }
+
+    /// Append IMAGE GIFtags followed by `data`, splitting it at the NLOOP limit.
+    pub fn image(&mut self, data: &[u128], eop: bool) -> &mut Self {
+        let chunks = data.chunks(MAX_NLOOP as usize);
+        let last = chunks.len().saturating_sub(1);
+        for (i, chunk) in chunks.enumerate() {
+            self.tag(GifTag::image(chunk.len() as u16).with_eop(eop && i == last));
+            self.qwords(chunk);
+        }
+        self
+    }
+
+    /// The written part of the buffer, for sending without finishing the packet.
+    pub fn contents(&mut self) -> &mut Aligned<A16, [u128]> {
+        let start = self.buffer.as_mut_ptr();
+        // As in `finish`, the written part of the buffer is itself aligned.
+        unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start, self.len) as *mut Aligned<A16, [u128]>)
+        }
+    }
+
+    /// Finish the packet, returning the written part of the buffer.
+    pub fn finish(self) -> &'a mut Aligned<A16, [u128]> {
+        let start = self.buffer.as_mut_ptr();
+        // The written part of the buffer begins at the same (aligned) address as the buffer, so
+        // it can be reinterpreted as a shorter aligned slice.
+        unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start, self.len) as *mut Aligned<A16, [u128]>)
+        }
+    }
+}
+
+/// The `i`th run of at most `MAX_NLOOP` loops of `per_loop` elements each in `data`.
+fn nloop_chunk<T>(data: &[T], i: usize, per_loop: usize) -> &[T] {
+    let len = MAX_NLOOP as usize * per_loop;
+    let start = i * len;
+    &data[start..data.len().min(start + len)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_encoding() {
+        let tag = GifTag::packed(3, &[Descriptor::RGBAQ, Descriptor::XYZ2])
+            .with_eop(true)
+            .with_prim(0x6);
+
+        assert_eq!(tag.to_qword(), 0x0000_0000_0000_0051_2003_4000_0000_8003);
+        assert_eq!(tag.data_qwords(), 6);
+    }
+
+    #[test]
+    fn sixteen_registers_is_nreg_zero() {
+        let tag = GifTag::reglist(1, &[Descriptor::NOP; 16]);
+
+        assert_eq!(tag.header.nreg(), 0);
+        assert_eq!(tag.nreg(), 16);
+        assert_eq!(tag.regs, u64::MAX);
+    }
+
+    #[test]
+    fn ad_list() {
+        let mut buffer = Aligned([0u128; 4]);
+        let mut packet = GifPacket::new(&mut buffer);
+        packet.ad_list(&[(0x4C, 0x1234), (0x4E, 0x5678)], true);
+        let packet: &[u128] = packet.finish();
+
+        assert_eq!(
+            packet,
+            [
+                0x0000_0000_0000_000E_1000_0000_0000_8002,
+                0x0000_0000_0000_004C_0000_0000_0000_1234,
+                0x0000_0000_0000_004E_0000_0000_0000_5678,
+            ]
+        );
+    }
+
+    #[test]
+    fn registers_as_ad() {
+        use crate::registers::{FRAME, ZBUF};
+
+        let mut buffer = Aligned([0u128; 3]);
+        let mut packet = GifPacket::new(&mut buffer);
+        packet
+            .tag(GifTag::packed(2, &[Descriptor::AD]).with_eop(true))
+            .context_register(Context::Two, FRAME::new().with_fbp(0x46).with_fbw(10))
+            .context_register(Context::One, ZBUF::new().with_zbp(0x8C).with_zmsk(true));
+
+        assert_eq!(
+            packet.as_slice()[1],
+            0x0000_0000_0000_004D_0000_0000_000A_0046
+        );
+        assert_eq!(
+            packet.as_slice()[2],
+            0x0000_0000_0000_004E_0000_0001_0000_008C
+        );
+    }
+
+    #[test]
+    fn reglist_pads_odd_data() {
+        let mut buffer = Aligned([0u128; 4]);
+        let mut packet = GifPacket::new(&mut buffer);
+        packet.reglist(
+            &[Descriptor::RGBAQ, Descriptor::XYZ2, Descriptor::XYZ2],
+            &[1, 2, 3],
+            false,
+        );
+
+        assert_eq!(
+            packet.as_slice(),
+            [
+                0x0000_0000_0000_0551_3400_0000_0000_0001,
+                0x0000_0000_0000_0002_0000_0000_0000_0001,
+                0x0000_0000_0000_0000_0000_0000_0000_0003,
+            ]
+        );
+    }
+
+    #[test]
+    fn image_splits_at_nloop_limit() {
+        static mut BUFFER: Aligned<A16, [u128; 0x8002]> = Aligned([0; 0x8002]);
+        let data = [0xAAu128; 0x8000];
+
+        let mut packet = GifPacket::new(unsafe { &mut *ptr::addr_of_mut!(BUFFER) });
+        packet.image(&data, true);
+        let packet: &[u128] = packet.finish();
+
+        assert_eq!(packet.len(), 0x8002);
+        assert_eq!(packet[0], 0x0800_0000_0000_7FFF);
+        assert_eq!(packet[1], 0xAA);
+        assert_eq!(packet[0x8000], 0x0800_0000_0000_8001);
+        assert_eq!(packet[0x8001], 0xAA);
+    }
+
+    #[test]
+    fn ad_list_splits_at_nloop_limit() {
+        static mut BUFFER: Aligned<A16, [u128; 0x8002]> = Aligned([0; 0x8002]);
+        static PAIRS: [(u8, u64); 0x8000] = [(0x4C, 0x1234); 0x8000];
+
+        let mut packet = GifPacket::new(unsafe { &mut *ptr::addr_of_mut!(BUFFER) });
+        packet.ad_list(&PAIRS, true);
+        let packet: &[u128] = packet.finish();
+
+        assert_eq!(packet.len(), 0x8002);
+        assert_eq!(packet[0], 0x0000_0000_0000_000E_1000_0000_0000_7FFF);
+        assert_eq!(packet[0x8000], 0x0000_0000_0000_000E_1000_0000_0000_8001);
+        assert_eq!(packet[0x8001], 0x0000_0000_0000_004C_0000_0000_0000_1234);
+    }
+
+    #[test]
+    #[should_panic(expected = "REGLIST needs at least one register")]
+    fn reglist_without_registers() {
+        let mut buffer = Aligned([0u128; 1]);
+        GifPacket::new(&mut buffer).reglist(&[], &[], true);
+    }
+}
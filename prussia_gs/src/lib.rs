@@ -3,6 +3,16 @@
 #![no_std]
 #![deny(missing_docs)]
 
+pub mod clut;
+pub mod display;
+pub mod draw;
+pub mod gif;
+pub mod registers;
+pub mod swap;
+pub mod swizzle;
+pub mod transfer;
+pub mod vram;
+
 /// Wrappers around GS privileged registers.
 pub mod privileged {
     use bitfield_struct::bitfield;
@@ -33,7 +43,7 @@ pub mod privileged {
     pub struct PMODE {
         pub en1: bool,
         pub en2: bool,
-        #[bits(3, default = 001)]
+        #[bits(3, default = 1)]
         pub crtmd: u8,
         pub mmod: bool,
         pub amod: bool,
@@ -479,7 +489,7 @@ pub mod privileged {
         /// Read GS Status Register
         pub fn load() -> Self {
             let csr = unsafe { ptr::read_volatile(CSR_ADDR) };
-            return CSR::from(csr);
+            CSR::from(csr)
         }
     }
 
@@ -544,7 +554,7 @@ pub mod privileged {
         /// Read the Singal ID Value Register
         pub fn load() -> Self {
             let siglblid = unsafe { ptr::read_volatile(SIGLBLID_ADDR) };
-            return SIGLBLID::from(siglblid);
+            SIGLBLID::from(siglblid)
         }
     }
 }
@@ -0,0 +1,954 @@
+//! General-purpose GS registers.
+//!
+//! Unlike the privileged registers, these are not memory-mapped: they are written through the GIF,
+//! usually as A+D elements of a PACKED GIFtag (see `gif::GifPacket::register`). Each type here
+//! knows its own register address through the `Register` trait, or through the `ContextRegister`
+//! trait for registers that exist once per drawing context.
+//!
+//! Floating-point fields (`RGBAQ::q`, `ST::s` and `ST::t`) hold the bits of an IEEE 754 single,
+//! as returned by `f32::to_bits`.
+
+use bitfield_struct::bitfield;
+
+/// A general-purpose GS register.
+pub trait Register: Copy + Into<u64> {
+    /// The address of this register, as used in an A+D element.
+    const ADDRESS: u8;
+}
+
+/// A general-purpose GS register which exists once for each drawing context.
+pub trait ContextRegister: Copy + Into<u64> {
+    /// The address of this register for drawing context 1.
+    const ADDRESS_1: u8;
+    /// The address of this register for drawing context 2.
+    const ADDRESS_2: u8;
+
+    /// The address of this register for the given drawing context.
+    fn address(context: Context) -> u8 {
+        match context {
+            Context::One => Self::ADDRESS_1,
+            Context::Two => Self::ADDRESS_2,
+        }
+    }
+}
+
+/// The GS drawing contexts, selected by `PRIM::ctxt`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+    /// Drawing context 1.
+    One,
+    /// Drawing context 2.
+    Two,
+}
+
+/// Pixel storage modes, as used in the PSM fields of the GS registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Psm {
+    /// 32-bit RGBA colour.
+    PSMCT32 = 0x00,
+    /// 24-bit RGB colour.
+    PSMCT24 = 0x01,
+    /// 16-bit RGBA5551 colour.
+    PSMCT16 = 0x02,
+    /// 16-bit RGBA5551 colour, in a different arrangement to PSMCT16.
+    PSMCT16S = 0x0A,
+    /// 8-bit CLUT index.
+    PSMT8 = 0x13,
+    /// 4-bit CLUT index.
+    PSMT4 = 0x14,
+    /// 8-bit CLUT index, stored in the upper 8 bits of a 32-bit pixel.
+    PSMT8H = 0x1B,
+    /// 4-bit CLUT index, stored in bits 24 to 27 of a 32-bit pixel.
+    PSMT4HL = 0x24,
+    /// 4-bit CLUT index, stored in bits 28 to 31 of a 32-bit pixel.
+    PSMT4HH = 0x2C,
+    /// 32-bit depth.
+    PSMZ32 = 0x30,
+    /// 24-bit depth.
+    PSMZ24 = 0x31,
+    /// 16-bit depth.
+    PSMZ16 = 0x32,
+    /// 16-bit depth, in a different arrangement to PSMZ16.
+    PSMZ16S = 0x3A,
+}
+
+impl Psm {
+    /// The number of bits each pixel occupies in GS memory.
+    pub fn bits_per_pixel(self) -> u32 {
+        match self {
+            Psm::PSMCT32
+            | Psm::PSMCT24
+            | Psm::PSMT8H
+            | Psm::PSMT4HL
+            | Psm::PSMT4HH
+            | Psm::PSMZ32
+            | Psm::PSMZ24 => 32,
+            Psm::PSMCT16 | Psm::PSMCT16S | Psm::PSMZ16 | Psm::PSMZ16S => 16,
+            Psm::PSMT8 => 8,
+            Psm::PSMT4 => 4,
+        }
+    }
+
+    /// The width and height in pixels of a 2048-word page.
+    pub fn page_size(self) -> (u32, u32) {
+        match self.bits_per_pixel() {
+            32 => (64, 32),
+            16 => (64, 64),
+            8 => (128, 64),
+            _ => (128, 128),
+        }
+    }
+
+    /// The width and height in pixels of a 64-word block.
+    pub fn block_size(self) -> (u32, u32) {
+        match self.bits_per_pixel() {
+            32 => (8, 8),
+            16 => (16, 8),
+            8 => (16, 16),
+            _ => (32, 16),
+        }
+    }
+
+    /// Whether this is a depth format.
+    pub fn is_depth(self) -> bool {
+        matches!(self, Psm::PSMZ32 | Psm::PSMZ24 | Psm::PSMZ16 | Psm::PSMZ16S)
+    }
+}
+
+/// Drawing primitive types, as used in `PRIM::prim`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    /// A single point per vertex.
+    Point = 0,
+    /// A line between each pair of vertices.
+    Line = 1,
+    /// A line between each vertex and the one before it.
+    LineStrip = 2,
+    /// A triangle between each three vertices.
+    Triangle = 3,
+    /// A triangle between each vertex and the two before it.
+    TriangleStrip = 4,
+    /// A triangle between each vertex, the one before it and the first vertex.
+    TriangleFan = 5,
+    /// An axis-aligned rectangle between each pair of opposite corners.
+    Sprite = 6,
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Drawing Primitive Setting
+pub struct PRIM {
+    #[bits(3)]
+    pub prim: u8,
+    pub iip: bool,
+    pub tme: bool,
+    pub fge: bool,
+    pub abe: bool,
+    pub aa1: bool,
+    pub fst: bool,
+    pub ctxt: bool,
+    pub fix: bool,
+    #[bits(53)]
+    __: u64,
+}
+
+impl Register for PRIM {
+    const ADDRESS: u8 = 0x00;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Colour Setting
+pub struct RGBAQ {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+    pub q: u32,
+}
+
+impl Register for RGBAQ {
+    const ADDRESS: u8 = 0x01;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Texture Coordinate Setting (Texture Coordinates)
+pub struct ST {
+    pub s: u32,
+    pub t: u32,
+}
+
+impl Register for ST {
+    const ADDRESS: u8 = 0x02;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Texture Coordinate Setting (Texel Coordinates)
+pub struct UV {
+    #[bits(14)]
+    pub u: u16,
+    #[bits(2)]
+    __: u8,
+    #[bits(14)]
+    pub v: u16,
+    #[bits(34)]
+    __: u64,
+}
+
+impl Register for UV {
+    const ADDRESS: u8 = 0x03;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Coordinate Value Setting (With Drawing Kick)
+pub struct XYZF2 {
+    pub x: u16,
+    pub y: u16,
+    #[bits(24)]
+    pub z: u32,
+    pub f: u8,
+}
+
+impl Register for XYZF2 {
+    const ADDRESS: u8 = 0x04;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Coordinate Value Setting (With Drawing Kick)
+pub struct XYZ2 {
+    pub x: u16,
+    pub y: u16,
+    pub z: u32,
+}
+
+impl Register for XYZ2 {
+    const ADDRESS: u8 = 0x05;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Information Setting
+pub struct TEX0 {
+    #[bits(14)]
+    pub tbp0: u16,
+    #[bits(6)]
+    pub tbw: u8,
+    #[bits(6)]
+    pub psm: u8,
+    #[bits(4)]
+    pub tw: u8,
+    #[bits(4)]
+    pub th: u8,
+    pub tcc: bool,
+    #[bits(2)]
+    pub tfx: u8,
+    #[bits(14)]
+    pub cbp: u16,
+    #[bits(4)]
+    pub cpsm: u8,
+    pub csm: bool,
+    #[bits(5)]
+    pub csa: u8,
+    #[bits(3)]
+    pub cld: u8,
+}
+
+impl ContextRegister for TEX0 {
+    const ADDRESS_1: u8 = 0x06;
+    const ADDRESS_2: u8 = 0x07;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Wrap Mode Setting
+pub struct CLAMP {
+    #[bits(2)]
+    pub wms: u8,
+    #[bits(2)]
+    pub wmt: u8,
+    #[bits(10)]
+    pub minu: u16,
+    #[bits(10)]
+    pub maxu: u16,
+    #[bits(10)]
+    pub minv: u16,
+    #[bits(10)]
+    pub maxv: u16,
+    #[bits(20)]
+    __: u32,
+}
+
+impl ContextRegister for CLAMP {
+    const ADDRESS_1: u8 = 0x08;
+    const ADDRESS_2: u8 = 0x09;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Fog Value Setting
+pub struct FOG {
+    #[bits(56)]
+    __: u64,
+    pub f: u8,
+}
+
+impl Register for FOG {
+    const ADDRESS: u8 = 0x0A;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Coordinate Value Setting (Without Drawing Kick)
+pub struct XYZF3 {
+    pub x: u16,
+    pub y: u16,
+    #[bits(24)]
+    pub z: u32,
+    pub f: u8,
+}
+
+impl Register for XYZF3 {
+    const ADDRESS: u8 = 0x0C;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Vertex Coordinate Value Setting (Without Drawing Kick)
+pub struct XYZ3 {
+    pub x: u16,
+    pub y: u16,
+    pub z: u32,
+}
+
+impl Register for XYZ3 {
+    const ADDRESS: u8 = 0x0D;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Information Setting
+pub struct TEX1 {
+    pub lcm: bool,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub mxl: u8,
+    pub mmag: bool,
+    #[bits(3)]
+    pub mmin: u8,
+    pub mtba: bool,
+    #[bits(9)]
+    __: u16,
+    #[bits(2)]
+    pub l: u8,
+    #[bits(11)]
+    __: u16,
+    #[bits(12)]
+    pub k: u16,
+    #[bits(20)]
+    __: u32,
+}
+
+impl ContextRegister for TEX1 {
+    const ADDRESS_1: u8 = 0x14;
+    const ADDRESS_2: u8 = 0x15;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Information Setting
+pub struct TEX2 {
+    #[bits(20)]
+    __: u32,
+    #[bits(6)]
+    pub psm: u8,
+    #[bits(11)]
+    __: u16,
+    #[bits(14)]
+    pub cbp: u16,
+    #[bits(4)]
+    pub cpsm: u8,
+    pub csm: bool,
+    #[bits(5)]
+    pub csa: u8,
+    #[bits(3)]
+    pub cld: u8,
+}
+
+impl ContextRegister for TEX2 {
+    const ADDRESS_1: u8 = 0x16;
+    const ADDRESS_2: u8 = 0x17;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Offset Value Setting
+pub struct XYOFFSET {
+    pub ofx: u16,
+    #[bits(16)]
+    __: u16,
+    pub ofy: u16,
+    #[bits(16)]
+    __: u16,
+}
+
+impl ContextRegister for XYOFFSET {
+    const ADDRESS_1: u8 = 0x18;
+    const ADDRESS_2: u8 = 0x19;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Specification of Primitive Attribute Setting Method
+pub struct PRMODECONT {
+    pub ac: bool,
+    #[bits(63)]
+    __: u64,
+}
+
+impl Register for PRMODECONT {
+    const ADDRESS: u8 = 0x1A;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Drawing Primitive Attribute Setting
+pub struct PRMODE {
+    #[bits(3)]
+    __: u8,
+    pub iip: bool,
+    pub tme: bool,
+    pub fge: bool,
+    pub abe: bool,
+    pub aa1: bool,
+    pub fst: bool,
+    pub ctxt: bool,
+    pub fix: bool,
+    #[bits(53)]
+    __: u64,
+}
+
+impl Register for PRMODE {
+    const ADDRESS: u8 = 0x1B;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// CLUT Position Specification
+pub struct TEXCLUT {
+    #[bits(6)]
+    pub cbw: u8,
+    #[bits(6)]
+    pub cou: u8,
+    #[bits(10)]
+    pub cov: u16,
+    #[bits(42)]
+    __: u64,
+}
+
+impl Register for TEXCLUT {
+    const ADDRESS: u8 = 0x1C;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Raster Address Mask Setting
+pub struct SCANMSK {
+    #[bits(2)]
+    pub msk: u8,
+    #[bits(62)]
+    __: u64,
+}
+
+impl Register for SCANMSK {
+    const ADDRESS: u8 = 0x22;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// MIPMAP Information Setting (Levels 1 to 3)
+pub struct MIPTBP1 {
+    #[bits(14)]
+    pub tbp1: u16,
+    #[bits(6)]
+    pub tbw1: u8,
+    #[bits(14)]
+    pub tbp2: u16,
+    #[bits(6)]
+    pub tbw2: u8,
+    #[bits(14)]
+    pub tbp3: u16,
+    #[bits(6)]
+    pub tbw3: u8,
+    #[bits(4)]
+    __: u8,
+}
+
+impl ContextRegister for MIPTBP1 {
+    const ADDRESS_1: u8 = 0x34;
+    const ADDRESS_2: u8 = 0x35;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// MIPMAP Information Setting (Levels 4 to 6)
+pub struct MIPTBP2 {
+    #[bits(14)]
+    pub tbp4: u16,
+    #[bits(6)]
+    pub tbw4: u8,
+    #[bits(14)]
+    pub tbp5: u16,
+    #[bits(6)]
+    pub tbw5: u8,
+    #[bits(14)]
+    pub tbp6: u16,
+    #[bits(6)]
+    pub tbw6: u8,
+    #[bits(4)]
+    __: u8,
+}
+
+impl ContextRegister for MIPTBP2 {
+    const ADDRESS_1: u8 = 0x36;
+    const ADDRESS_2: u8 = 0x37;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Alpha Value Setting
+pub struct TEXA {
+    pub ta0: u8,
+    #[bits(7)]
+    __: u8,
+    pub aem: bool,
+    #[bits(16)]
+    __: u16,
+    pub ta1: u8,
+    #[bits(24)]
+    __: u32,
+}
+
+impl Register for TEXA {
+    const ADDRESS: u8 = 0x3B;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Distant Fog Colour Setting
+pub struct FOGCOL {
+    pub fcr: u8,
+    pub fcg: u8,
+    pub fcb: u8,
+    #[bits(40)]
+    __: u64,
+}
+
+impl Register for FOGCOL {
+    const ADDRESS: u8 = 0x3D;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Texture Page Buffer Disabling
+pub struct TEXFLUSH {
+    #[bits(64)]
+    __: u64,
+}
+
+impl Register for TEXFLUSH {
+    const ADDRESS: u8 = 0x3F;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Scissoring Area Setting
+pub struct SCISSOR {
+    #[bits(11)]
+    pub scax0: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub scax1: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub scay0: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub scay1: u16,
+    #[bits(5)]
+    __: u8,
+}
+
+impl ContextRegister for SCISSOR {
+    const ADDRESS_1: u8 = 0x40;
+    const ADDRESS_2: u8 = 0x41;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Alpha Blending Setting
+pub struct ALPHA {
+    #[bits(2)]
+    pub a: u8,
+    #[bits(2)]
+    pub b: u8,
+    #[bits(2)]
+    pub c: u8,
+    #[bits(2)]
+    pub d: u8,
+    #[bits(24)]
+    __: u32,
+    pub fix: u8,
+    #[bits(24)]
+    __: u32,
+}
+
+impl ContextRegister for ALPHA {
+    const ADDRESS_1: u8 = 0x42;
+    const ADDRESS_2: u8 = 0x43;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Dither Matrix Setting
+pub struct DIMX {
+    #[bits(3)]
+    pub dm00: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm01: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm02: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm03: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm10: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm11: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm12: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm13: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm20: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm21: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm22: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm23: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm30: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm31: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm32: u8,
+    #[bits(1)]
+    __: u8,
+    #[bits(3)]
+    pub dm33: u8,
+    #[bits(1)]
+    __: u8,
+}
+
+impl Register for DIMX {
+    const ADDRESS: u8 = 0x44;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Dither Control
+pub struct DTHE {
+    pub dthe: bool,
+    #[bits(63)]
+    __: u64,
+}
+
+impl Register for DTHE {
+    const ADDRESS: u8 = 0x45;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Colour Clamp Control
+pub struct COLCLAMP {
+    pub clamp: bool,
+    #[bits(63)]
+    __: u64,
+}
+
+impl Register for COLCLAMP {
+    const ADDRESS: u8 = 0x46;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Pixel Test Control
+pub struct TEST {
+    pub ate: bool,
+    #[bits(3)]
+    pub atst: u8,
+    pub aref: u8,
+    #[bits(2)]
+    pub afail: u8,
+    pub date: bool,
+    pub datm: bool,
+    pub zte: bool,
+    #[bits(2)]
+    pub ztst: u8,
+    #[bits(45)]
+    __: u64,
+}
+
+impl ContextRegister for TEST {
+    const ADDRESS_1: u8 = 0x47;
+    const ADDRESS_2: u8 = 0x48;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Alpha Blending Control in Units of Pixels
+pub struct PABE {
+    pub pabe: bool,
+    #[bits(63)]
+    __: u64,
+}
+
+impl Register for PABE {
+    const ADDRESS: u8 = 0x49;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Alpha Correction Value
+pub struct FBA {
+    pub fba: bool,
+    #[bits(63)]
+    __: u64,
+}
+
+impl ContextRegister for FBA {
+    const ADDRESS_1: u8 = 0x4A;
+    const ADDRESS_2: u8 = 0x4B;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Frame Buffer Setting
+pub struct FRAME {
+    #[bits(9)]
+    pub fbp: u16,
+    #[bits(7)]
+    __: u8,
+    #[bits(6)]
+    pub fbw: u8,
+    #[bits(2)]
+    __: u8,
+    #[bits(6)]
+    pub psm: u8,
+    #[bits(2)]
+    __: u8,
+    pub fbmsk: u32,
+}
+
+impl ContextRegister for FRAME {
+    const ADDRESS_1: u8 = 0x4C;
+    const ADDRESS_2: u8 = 0x4D;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Z Buffer Setting
+pub struct ZBUF {
+    #[bits(9)]
+    pub zbp: u16,
+    #[bits(15)]
+    __: u16,
+    #[bits(4)]
+    pub psm: u8,
+    #[bits(4)]
+    __: u8,
+    pub zmsk: bool,
+    #[bits(31)]
+    __: u32,
+}
+
+impl ContextRegister for ZBUF {
+    const ADDRESS_1: u8 = 0x4E;
+    const ADDRESS_2: u8 = 0x4F;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Setting for Transmission Between Buffers
+pub struct BITBLTBUF {
+    #[bits(14)]
+    pub sbp: u16,
+    #[bits(2)]
+    __: u8,
+    #[bits(6)]
+    pub sbw: u8,
+    #[bits(2)]
+    __: u8,
+    #[bits(6)]
+    pub spsm: u8,
+    #[bits(2)]
+    __: u8,
+    #[bits(14)]
+    pub dbp: u16,
+    #[bits(2)]
+    __: u8,
+    #[bits(6)]
+    pub dbw: u8,
+    #[bits(2)]
+    __: u8,
+    #[bits(6)]
+    pub dpsm: u8,
+    #[bits(2)]
+    __: u8,
+}
+
+impl Register for BITBLTBUF {
+    const ADDRESS: u8 = 0x50;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Specification of Transmission Areas in Buffers
+pub struct TRXPOS {
+    #[bits(11)]
+    pub ssax: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub ssay: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub dsax: u16,
+    #[bits(5)]
+    __: u8,
+    #[bits(11)]
+    pub dsay: u16,
+    #[bits(2)]
+    pub dir: u8,
+    #[bits(3)]
+    __: u8,
+}
+
+impl Register for TRXPOS {
+    const ADDRESS: u8 = 0x51;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Specification of Transmission Area in Buffers
+pub struct TRXREG {
+    #[bits(12)]
+    pub rrw: u16,
+    #[bits(20)]
+    __: u32,
+    #[bits(12)]
+    pub rrh: u16,
+    #[bits(20)]
+    __: u32,
+}
+
+impl Register for TRXREG {
+    const ADDRESS: u8 = 0x52;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Activation of Transmission Between Buffers
+pub struct TRXDIR {
+    #[bits(2)]
+    pub xdir: u8,
+    #[bits(62)]
+    __: u64,
+}
+
+impl Register for TRXDIR {
+    const ADDRESS: u8 = 0x53;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// Data Port for Transmission Between Buffers
+pub struct HWREG {
+    pub data: u64,
+}
+
+impl Register for HWREG {
+    const ADDRESS: u8 = 0x54;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// SIGNAL Event Occurrence Request
+pub struct SIGNAL {
+    pub id: u32,
+    pub idmsk: u32,
+}
+
+impl Register for SIGNAL {
+    const ADDRESS: u8 = 0x60;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// FINISH Event Occurrence Request
+pub struct FINISH {
+    #[bits(64)]
+    __: u64,
+}
+
+impl Register for FINISH {
+    const ADDRESS: u8 = 0x61;
+}
+
+#[bitfield(u64)]
+#[derive(PartialEq, Eq)]
+/// LABEL Event Occurrence Request
+pub struct LABEL {
+    pub id: u32,
+    pub idmsk: u32,
+}
+
+impl Register for LABEL {
+    const ADDRESS: u8 = 0x62;
+}
@@ -0,0 +1,171 @@
+//! Multiple-buffered display output.
+//!
+//! Drawing straight into the framebuffer being displayed shows half-finished frames. Instead, a
+//! `SwapChain` cycles through two or more framebuffers: the GS draws into the back buffer (using
+//! the FRAME value from `SwapChain::frame`) while the PCRTC displays the front buffer, and
+//! `SwapChain::swap` exchanges them during vertical blank.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use prussia_gs::display::Display;
+//! use prussia_gs::swap::{SwapChain, VSync};
+//!
+//! // A 640x448 32-bit framebuffer takes 140 pages.
+//! let mut chain = SwapChain::new(Display::ntsc(), [0, 140]);
+//! loop {
+//!     let frame = chain.frame();
+//!     // Draw the next frame into `frame`.
+//!     chain.swap(VSync::Poll);
+//! }
+//! ```
+
+use core::hint;
+
+#[cfg(target_arch = "mips")]
+use prussia_bios::Event;
+
+use crate::display::Display;
+use crate::privileged::CSR;
+use crate::registers::FRAME;
+
+/// The most threads which can wait for vertical blank with `VSync::Interrupt` at once.
+pub const MAX_WAITERS: usize = 8;
+
+/// The VBON interrupts seen by `vblank_handler`.
+#[cfg(target_arch = "mips")]
+static VBLANK: Event<MAX_WAITERS> = Event::new();
+
+/// How to wait for vertical blank.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VSync {
+    /// Do not wait. The new frame may tear.
+    Off,
+    /// Poll `CSR.vsint` until vertical blank starts.
+    Poll,
+    /// Sleep the current thread until the next VBON interrupt, which must be routed to
+    /// `vblank_handler`. Other threads run in the meantime, and up to `MAX_WAITERS` of them can
+    /// wait for the same vertical blank.
+    Interrupt,
+}
+
+/// An INTC handler counting the start of vertical blank and waking the threads waiting for it,
+/// for `VSync::Interrupt`.
+///
+/// Register this for the VBON cause with `prussia_bios::add_intc_handler` and enable it with
+/// `prussia_bios::enable_intc`.
+pub fn vblank_handler(_cause: i32) -> i32 {
+    #[cfg(target_arch = "mips")]
+    VBLANK.isignal();
+    0
+}
+
+/// Wait until the start of the next vertical blank.
+///
+/// # Panics
+///
+/// Panics if `vsync` is `VSync::Interrupt` and `MAX_WAITERS` other threads are already waiting.
+pub fn wait_vsync(vsync: VSync) {
+    match vsync {
+        VSync::Off => {}
+        VSync::Poll => {
+            // VSINT is latched until cleared by writing 1 to it.
+            CSR::new().with_vsint(1).store();
+            while CSR::load().vsint() == 0 {
+                hint::spin_loop();
+            }
+        }
+        VSync::Interrupt => {
+            #[cfg(target_arch = "mips")]
+            {
+                let start = VBLANK.count();
+                VBLANK.sleep_until(|| VBLANK.count() != start);
+            }
+        }
+    }
+}
+
+/// A set of `N` framebuffers which are displayed in turn.
+pub struct SwapChain<const N: usize> {
+    display: Display,
+    pages: [u16; N],
+    front: usize,
+}
+
+impl<const N: usize> SwapChain<N> {
+    /// Create a swap chain over the framebuffers starting at `pages`, in units of 2048 words.
+    /// The first framebuffer is displayed first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are fewer than two framebuffers.
+    pub fn new(display: Display, pages: [u16; N]) -> Self {
+        assert!(N >= 2, "A swap chain needs at least two framebuffers");
+        SwapChain {
+            display,
+            pages,
+            front: 0,
+        }
+    }
+
+    /// The display configuration the framebuffers are shown with.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// The page of the framebuffer being displayed.
+    pub fn front(&self) -> u16 {
+        self.pages[self.front]
+    }
+
+    /// The page of the framebuffer to draw into, which will be displayed next.
+    pub fn back(&self) -> u16 {
+        self.pages[(self.front + 1) % N]
+    }
+
+    /// The FRAME register value for drawing into the back buffer.
+    pub fn frame(&self) -> FRAME {
+        FRAME::new()
+            .with_fbp(self.back())
+            .with_fbw(self.display.width.div_ceil(64) as u8)
+            .with_psm(self.display.psm as u8)
+    }
+
+    /// Wait for vertical blank as requested, then display the back buffer.
+    pub fn swap(&mut self, vsync: VSync) {
+        wait_vsync(vsync);
+        self.advance();
+        let regs = self.display.registers(self.front());
+        regs.dispfb1.store();
+        regs.dispfb2.store();
+    }
+
+    fn advance(&mut self) {
+        self.front = (self.front + 1) % N;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_rotate() {
+        let mut chain = SwapChain::new(Display::ntsc(), [0, 140, 280]);
+        assert_eq!((chain.front(), chain.back()), (0, 140));
+
+        chain.advance();
+        assert_eq!((chain.front(), chain.back()), (140, 280));
+
+        chain.advance();
+        chain.advance();
+        assert_eq!((chain.front(), chain.back()), (0, 140));
+    }
+
+    #[test]
+    fn frame_targets_back_buffer() {
+        let chain = SwapChain::new(Display::dtv720p(), [0, 240]);
+
+        assert_eq!(u64::from(chain.frame()), 0x0214_00F0);
+    }
+}
@@ -0,0 +1,376 @@
+//! GS local memory layouts, in software.
+//!
+//! GS local memory is not laid out row by row. Each pixel storage mode divides a buffer into pages,
+//! pages into blocks and blocks into columns, and orders each level differently:
+//!
+//! | Format               | Page     | Block  | Column |
+//! |----------------------|----------|--------|--------|
+//! | 32-bit (CT32, Z32..) | 64x32    | 8x8    | 8x2    |
+//! | 16-bit (CT16, Z16..) | 64x64    | 16x8   | 16x2   |
+//! | PSMT8                | 128x64   | 16x16  | 16x4   |
+//! | PSMT4                | 128x128  | 32x16  | 32x4   |
+//!
+//! PSMCT24, PSMZ24, PSMT8H, PSMT4HL and PSMT4HH share the 32-bit layout, but only use some of the
+//! bits of each word. This lets the same memory be viewed in several formats, such as keeping an
+//! 8-bit texture in the unused top byte of a 24-bit framebuffer.
+//!
+//! `pixel_address` finds where a pixel lives, and `swizzle` and `unswizzle` convert whole images
+//! between row-by-row order and an image of GS memory. Converting an image in with one format and
+//! back out with another reinterprets it the way the GS would.
+//!
+//! # Examples
+//!
+//! ```
+//! use prussia_gs::registers::Psm;
+//! use prussia_gs::swizzle::{swizzle, unswizzle};
+//!
+//! // A 16x16 PSMT8 texture fills one block of 64 words.
+//! let texels: [u32; 256] = core::array::from_fn(|i| i as u32);
+//! let mut memory = [0u32; 64];
+//! swizzle(Psm::PSMT8, 0, 2, 16, 16, &texels, &mut memory);
+//!
+//! let mut round_trip = [0u32; 256];
+//! unswizzle(Psm::PSMT8, 0, 2, 16, 16, &memory, &mut round_trip);
+//! assert_eq!(texels, round_trip);
+//! ```
+
+use crate::registers::Psm;
+use crate::vram::{block_in_page, BLOCKS_PER_PAGE, BLOCK_WORDS, WORDS};
+
+/// The word offset within a block of each pixel of a 32-bit format, indexed by `y % 8` and `x % 8`.
+#[rustfmt::skip]
+const COLUMNS_32: [[u8; 8]; 8] = [
+    [ 0,  1,  4,  5,  8,  9, 12, 13],
+    [ 2,  3,  6,  7, 10, 11, 14, 15],
+    [16, 17, 20, 21, 24, 25, 28, 29],
+    [18, 19, 22, 23, 26, 27, 30, 31],
+    [32, 33, 36, 37, 40, 41, 44, 45],
+    [34, 35, 38, 39, 42, 43, 46, 47],
+    [48, 49, 52, 53, 56, 57, 60, 61],
+    [50, 51, 54, 55, 58, 59, 62, 63],
+];
+
+/// The halfword offset within a block of each pixel of a 16-bit format, indexed by `y % 8` and
+/// `x % 16`.
+#[rustfmt::skip]
+const COLUMNS_16: [[u8; 16]; 8] = [
+    [  0,   2,   8,  10,  16,  18,  24,  26,   1,   3,   9,  11,  17,  19,  25,  27],
+    [  4,   6,  12,  14,  20,  22,  28,  30,   5,   7,  13,  15,  21,  23,  29,  31],
+    [ 32,  34,  40,  42,  48,  50,  56,  58,  33,  35,  41,  43,  49,  51,  57,  59],
+    [ 36,  38,  44,  46,  52,  54,  60,  62,  37,  39,  45,  47,  53,  55,  61,  63],
+    [ 64,  66,  72,  74,  80,  82,  88,  90,  65,  67,  73,  75,  81,  83,  89,  91],
+    [ 68,  70,  76,  78,  84,  86,  92,  94,  69,  71,  77,  79,  85,  87,  93,  95],
+    [ 96,  98, 104, 106, 112, 114, 120, 122,  97,  99, 105, 107, 113, 115, 121, 123],
+    [100, 102, 108, 110, 116, 118, 124, 126, 101, 103, 109, 111, 117, 119, 125, 127],
+];
+
+/// The byte offset within a block of each pixel of PSMT8, indexed by `y % 8` and `x % 16`. Rows
+/// 8-15 of a block are laid out like rows 0-7, 128 bytes further on.
+#[rustfmt::skip]
+const COLUMNS_8: [[u8; 16]; 8] = [
+    [  0,   4,  16,  20,  32,  36,  48,  52,   2,   6,  18,  22,  34,  38,  50,  54],
+    [  8,  12,  24,  28,  40,  44,  56,  60,  10,  14,  26,  30,  42,  46,  58,  62],
+    [ 33,  37,   1,   5,  49,  53,  17,  21,  35,  39,   3,   7,  51,  55,  19,  23],
+    [ 41,  45,   9,  13,  57,  61,  25,  29,  43,  47,  11,  15,  59,  63,  27,  31],
+    [ 96, 100, 112, 116,  64,  68,  80,  84,  98, 102, 114, 118,  66,  70,  82,  86],
+    [104, 108, 120, 124,  72,  76,  88,  92, 106, 110, 122, 126,  74,  78,  90,  94],
+    [ 65,  69,  81,  85,  97, 101, 113, 117,  67,  71,  83,  87,  99, 103, 115, 119],
+    [ 73,  77,  89,  93, 105, 109, 121, 125,  75,  79,  91,  95, 107, 111, 123, 127],
+];
+
+/// The nibble offset within a block of each pixel of PSMT4, indexed by `y % 8` and `x % 32`. Rows
+/// 8-15 of a block are laid out like rows 0-7, 256 nibbles further on.
+#[rustfmt::skip]
+const COLUMNS_4: [[u8; 32]; 8] = [
+    [
+          0,   8,  32,  40,  64,  72,  96, 104,
+          2,  10,  34,  42,  66,  74,  98, 106,
+          4,  12,  36,  44,  68,  76, 100, 108,
+          6,  14,  38,  46,  70,  78, 102, 110,
+    ],
+    [
+         16,  24,  48,  56,  80,  88, 112, 120,
+         18,  26,  50,  58,  82,  90, 114, 122,
+         20,  28,  52,  60,  84,  92, 116, 124,
+         22,  30,  54,  62,  86,  94, 118, 126,
+    ],
+    [
+         65,  73,  97, 105,   1,   9,  33,  41,
+         67,  75,  99, 107,   3,  11,  35,  43,
+         69,  77, 101, 109,   5,  13,  37,  45,
+         71,  79, 103, 111,   7,  15,  39,  47,
+    ],
+    [
+         81,  89, 113, 121,  17,  25,  49,  57,
+         83,  91, 115, 123,  19,  27,  51,  59,
+         85,  93, 117, 125,  21,  29,  53,  61,
+         87,  95, 119, 127,  23,  31,  55,  63,
+    ],
+    [
+        192, 200, 224, 232, 128, 136, 160, 168,
+        194, 202, 226, 234, 130, 138, 162, 170,
+        196, 204, 228, 236, 132, 140, 164, 172,
+        198, 206, 230, 238, 134, 142, 166, 174,
+    ],
+    [
+        208, 216, 240, 248, 144, 152, 176, 184,
+        210, 218, 242, 250, 146, 154, 178, 186,
+        212, 220, 244, 252, 148, 156, 180, 188,
+        214, 222, 246, 254, 150, 158, 182, 190,
+    ],
+    [
+        129, 137, 161, 169, 193, 201, 225, 233,
+        131, 139, 163, 171, 195, 203, 227, 235,
+        133, 141, 165, 173, 197, 205, 229, 237,
+        135, 143, 167, 175, 199, 207, 231, 239,
+    ],
+    [
+        145, 153, 177, 185, 209, 217, 241, 249,
+        147, 155, 179, 187, 211, 219, 243, 251,
+        149, 157, 181, 189, 213, 221, 245, 253,
+        151, 159, 183, 191, 215, 223, 247, 255,
+    ],
+];
+
+/// Where a pixel is stored in GS local memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelAddress {
+    /// The word address, counting from the start of local memory.
+    pub word: u32,
+    /// The bit position of the pixel within the word.
+    pub shift: u32,
+    /// The number of bits the pixel occupies.
+    pub bits: u32,
+}
+
+impl PixelAddress {
+    /// The mask selecting the pixel's bits in its word.
+    pub fn mask(&self) -> u32 {
+        (((1u64 << self.bits) - 1) as u32) << self.shift
+    }
+}
+
+/// Find where pixel (`x`, `y`) of the buffer at block `bp`, `bw` units of 64 pixels wide, is
+/// stored.
+///
+/// Addresses wrap around at the end of local memory, as they do on the GS.
+pub fn pixel_address(psm: Psm, bp: u32, bw: u32, x: u32, y: u32) -> PixelAddress {
+    let (page_width, page_height) = psm.page_size();
+    let (block_width, block_height) = psm.block_size();
+    let (blocks_across, blocks_down) = (page_width / block_width, page_height / block_height);
+
+    // Pages are laid out row by row, `bw` 64-pixel units to a row.
+    let page = (y / page_height) * (bw * 64 / page_width) + x / page_width;
+    let block_x = (x / block_width) % blocks_across;
+    let block_y = (y / block_height) % blocks_down;
+    let block = (bp + page * BLOCKS_PER_PAGE + block_in_page(psm, block_x, block_y))
+        % (WORDS / BLOCK_WORDS);
+    let base = block * BLOCK_WORDS;
+
+    match psm.bits_per_pixel() {
+        32 => {
+            let word = base + COLUMNS_32[(y % 8) as usize][(x % 8) as usize] as u32;
+            let (shift, bits) = match psm {
+                Psm::PSMCT24 | Psm::PSMZ24 => (0, 24),
+                Psm::PSMT8H => (24, 8),
+                Psm::PSMT4HL => (24, 4),
+                Psm::PSMT4HH => (28, 4),
+                _ => (0, 32),
+            };
+            PixelAddress { word, shift, bits }
+        }
+        16 => {
+            let halfword = COLUMNS_16[(y % 8) as usize][(x % 16) as usize] as u32;
+            PixelAddress {
+                word: base + halfword / 2,
+                shift: 16 * (halfword % 2),
+                bits: 16,
+            }
+        }
+        8 => {
+            let byte = COLUMNS_8[(y % 8) as usize][(x % 16) as usize] as u32 + 128 * ((y / 8) % 2);
+            PixelAddress {
+                word: base + byte / 4,
+                shift: 8 * (byte % 4),
+                bits: 8,
+            }
+        }
+        _ => {
+            let nibble =
+                COLUMNS_4[(y % 8) as usize][(x % 32) as usize] as u32 + 256 * ((y / 8) % 2);
+            PixelAddress {
+                word: base + nibble / 8,
+                shift: 4 * (nibble % 8),
+                bits: 4,
+            }
+        }
+    }
+}
+
+/// The index into `memory` of `address`, where `memory` starts at block `bp`.
+fn memory_index(address: PixelAddress, bp: u32, memory_len: usize) -> usize {
+    let index = (address.word + WORDS - bp * BLOCK_WORDS) % WORDS;
+    assert!(
+        (index as usize) < memory_len,
+        "Word {:#x} is outside the memory image",
+        address.word
+    );
+    index as usize
+}
+
+/// Write a `width` by `height` image into `memory`, which holds GS local memory from block `bp`
+/// onwards, as the GS would store it in a `psm` buffer at `bp` that is `bw` units of 64 pixels
+/// wide.
+///
+/// `pixels` holds one pixel per element, row by row, in the low bits of each element. Bits of
+/// `memory` not used by the format are left alone.
+///
+/// # Panics
+///
+/// Panics if `pixels` is smaller than the image, or `memory` is too small to hold it.
+pub fn swizzle(
+    psm: Psm,
+    bp: u32,
+    bw: u32,
+    width: u32,
+    height: u32,
+    pixels: &[u32],
+    memory: &mut [u32],
+) {
+    assert!(
+        pixels.len() >= (width * height) as usize,
+        "Pixel data is smaller than the image"
+    );
+    for y in 0..height {
+        for x in 0..width {
+            let address = pixel_address(psm, bp, bw, x, y);
+            let word = &mut memory[memory_index(address, bp, memory.len())];
+            let pixel = pixels[(y * width + x) as usize];
+            *word = (*word & !address.mask()) | ((pixel << address.shift) & address.mask());
+        }
+    }
+}
+
+/// Read a `width` by `height` image out of `memory`, which holds GS local memory from block `bp`
+/// onwards, as the GS would read it from a `psm` buffer at `bp` that is `bw` units of 64 pixels
+/// wide.
+///
+/// Each pixel is written to `pixels` row by row, in the low bits of each element.
+///
+/// # Panics
+///
+/// Panics if `pixels` is smaller than the image, or `memory` is too small to hold it.
+pub fn unswizzle(
+    psm: Psm,
+    bp: u32,
+    bw: u32,
+    width: u32,
+    height: u32,
+    memory: &[u32],
+    pixels: &mut [u32],
+) {
+    assert!(
+        pixels.len() >= (width * height) as usize,
+        "Pixel buffer is smaller than the image"
+    );
+    for y in 0..height {
+        for x in 0..width {
+            let address = pixel_address(psm, bp, bw, x, y);
+            let word = memory[memory_index(address, bp, memory.len())];
+            pixels[(y * width + x) as usize] = (word & address.mask()) >> address.shift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(psm: Psm, bw: u32, x: u32, y: u32) -> (u32, u32) {
+        let address = pixel_address(psm, 0, bw, x, y);
+        (address.word, address.shift)
+    }
+
+    #[test]
+    fn psmct32_addresses() {
+        assert_eq!(word(Psm::PSMCT32, 1, 1, 0), (1, 0));
+        assert_eq!(word(Psm::PSMCT32, 1, 2, 0), (4, 0));
+        assert_eq!(word(Psm::PSMCT32, 1, 0, 1), (2, 0));
+        assert_eq!(word(Psm::PSMCT32, 1, 0, 2), (16, 0));
+        // Block 1 is to the right of block 0, block 2 below it.
+        assert_eq!(word(Psm::PSMCT32, 1, 8, 0), (64, 0));
+        assert_eq!(word(Psm::PSMCT32, 1, 0, 8), (128, 0));
+        // A 640 pixel wide buffer has ten pages to a row.
+        assert_eq!(word(Psm::PSMCT32, 10, 64, 0), (2048, 0));
+        assert_eq!(word(Psm::PSMCT32, 10, 0, 32), (10 * 2048, 0));
+        assert_eq!(word(Psm::PSMZ32, 10, 0, 0), (24 * 64, 0));
+    }
+
+    #[test]
+    fn sub_word_addresses() {
+        assert_eq!(word(Psm::PSMCT16, 1, 1, 0), (1, 0));
+        assert_eq!(word(Psm::PSMCT16, 1, 8, 0), (0, 16));
+        assert_eq!(word(Psm::PSMCT16, 1, 16, 0), (2 * 64, 0));
+        assert_eq!(word(Psm::PSMT8, 2, 0, 2), (8, 8));
+        assert_eq!(word(Psm::PSMT8, 2, 0, 8), (32, 0));
+        assert_eq!(word(Psm::PSMT4, 2, 1, 0), (1, 0));
+        assert_eq!(word(Psm::PSMT4, 2, 0, 2), (8, 4));
+        assert_eq!(word(Psm::PSMT8H, 1, 1, 0), (1, 24));
+        assert_eq!(word(Psm::PSMT4HH, 1, 0, 1), (2, 28));
+    }
+
+    #[test]
+    fn pages_are_permutations() {
+        let formats = [
+            Psm::PSMCT32,
+            Psm::PSMCT16,
+            Psm::PSMCT16S,
+            Psm::PSMT8,
+            Psm::PSMT4,
+            Psm::PSMZ32,
+            Psm::PSMZ16,
+            Psm::PSMZ16S,
+        ];
+        for psm in formats {
+            let (width, height) = psm.page_size();
+            let mut seen = [0u32; 2048];
+            for y in 0..height {
+                for x in 0..width {
+                    let address = pixel_address(psm, 0, width / 64, x, y);
+                    assert_eq!(seen[address.word as usize] & address.mask(), 0);
+                    seen[address.word as usize] |= address.mask();
+                }
+            }
+            assert!(seen.iter().all(|&word| word == !0), "{:?}", psm);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let pixels: [u32; 64 * 64] = core::array::from_fn(|i| (i as u32).wrapping_mul(0x9E37_79B9));
+        let mut memory = [0u32; 4096];
+        let mut out = [0u32; 64 * 64];
+
+        swizzle(Psm::PSMCT16S, 32, 1, 64, 64, &pixels, &mut memory);
+        unswizzle(Psm::PSMCT16S, 32, 1, 64, 64, &memory, &mut out);
+        assert!(pixels.iter().zip(&out).all(|(a, b)| a & 0xFFFF == *b));
+    }
+
+    #[test]
+    fn reinterpret_between_formats() {
+        // Fill a 24-bit framebuffer, then store an 8-bit texture in its top byte.
+        let colours = [0x00AB_CDEFu32; 64];
+        let texels: [u32; 64] = core::array::from_fn(|i| i as u32);
+        let mut memory = [0u32; 64];
+
+        swizzle(Psm::PSMCT24, 0, 1, 8, 8, &colours, &mut memory);
+        swizzle(Psm::PSMT8H, 0, 1, 8, 8, &texels, &mut memory);
+
+        let mut out = [0u32; 64];
+        unswizzle(Psm::PSMCT32, 0, 1, 8, 8, &memory, &mut out);
+        assert!(out
+            .iter()
+            .enumerate()
+            .all(|(i, &pixel)| pixel == ((i as u32) << 24) | 0xAB_CDEF));
+    }
+}
@@ -0,0 +1,485 @@
+//! Transfers between EE memory and GS local memory.
+//!
+//! A host-to-local transfer is set up by writing four registers:
+//!
+//! - BITBLTBUF: the destination buffer's base pointer (in blocks), width and storage mode.
+//! - TRXPOS: the top-left corner of the destination rectangle.
+//! - TRXREG: the size of the rectangle.
+//! - TRXDIR: the transfer direction, which starts the transfer.
+//!
+//! The pixel data then follows in IMAGE mode GIFtags, each of which holds at most `MAX_NLOOP`
+//! quadwords. `Upload` describes the destination and writes the setup, and `upload` sends the whole
+//! thing over the GIF DMA channel without copying the pixels.
+//!
+//! A local-to-host transfer uses the same registers with the source fields filled in instead.
+//! The GS then sends the pixels back through the VIF1 FIFO, which `download_rect` reads with the
+//! host bus direction (BUSDIR) reversed, for screenshots or CPU post-processing.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_gs::registers::Psm;
+//! use prussia_gs::transfer::{upload, Rect, Upload};
+//!
+//! fn upload_font(gif: prussia_dma::Gif, pixels: &mut Aligned<A16, [u32]>) -> prussia_dma::Gif {
+//!     // A 128x64 32-bit texture at block 0x2000.
+//!     let dest = Upload::new(0x2000, 2, Psm::PSMCT32, Rect::new(0, 0, 128, 64));
+//!     upload(gif, &dest, pixels)
+//! }
+//! ```
+
+use core::{mem, ptr};
+
+use aligned::{Aligned, A16};
+use prussia_dma::{Gif, Transfer, Vif1};
+
+use crate::gif::{Descriptor, GifPacket, GifTag, MAX_NLOOP};
+use crate::privileged::{BUSDIR, CSR};
+use crate::registers::{Psm, BITBLTBUF, FINISH, TEXFLUSH, TRXDIR, TRXPOS, TRXREG};
+use crate::vram::{buffer_width, FrameBuffer, Texture, BLOCKS_PER_PAGE};
+
+/// VIF1 Status, whose FDR bit reverses the VIF1 FIFO for local-to-host transfers.
+const VIF1_STAT: *mut u32 = 0x1000_3C00 as *mut u32;
+const VIF1_STAT_FDR: u32 = 1 << 23;
+
+/// The most quadwords a single DMA transfer can move, as QWC is 16 bits wide.
+const MAX_QWC: usize = 0xFFFF;
+
+/// A rectangle of pixels in a GS buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    /// The left edge, in pixels.
+    pub x: u16,
+    /// The top edge, in pixels.
+    pub y: u16,
+    /// The width, in pixels.
+    pub width: u16,
+    /// The height, in pixels.
+    pub height: u16,
+}
+
+impl Rect {
+    /// Create a rectangle of `width` by `height` pixels with its top-left corner at (`x`, `y`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the corner is outside the 2048x2048 GS coordinate space, or the size is larger
+    /// than it.
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        assert!(x < 2048 && y < 2048, "Transfer position is out of range");
+        assert!(
+            width <= 2048 && height <= 2048,
+            "Transfer size is out of range"
+        );
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The number of pixels in the rectangle.
+    pub fn pixels(&self) -> u32 {
+        self.width as u32 * self.height as u32
+    }
+}
+
+/// The number of quadwords in `rect` of pixels stored as `psm`.
+fn image_qwords(rect: Rect, psm: Psm) -> usize {
+    let bits = rect.pixels() * psm.bits_per_pixel();
+    assert!(
+        bits.is_multiple_of(128),
+        "A {}x{} {:?} transfer is not a whole number of quadwords",
+        rect.width,
+        rect.height,
+        psm
+    );
+    (bits / 128) as usize
+}
+
+/// Transfer directions, as written to TRXDIR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From EE memory to GS local memory.
+    HostToLocal = 0,
+    /// From GS local memory to EE memory.
+    LocalToHost = 1,
+    /// From GS local memory to GS local memory.
+    LocalToLocal = 2,
+    /// No transfer.
+    Deactivated = 3,
+}
+
+/// The destination of a host-to-local transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Upload {
+    /// The destination base pointer, in blocks.
+    pub dbp: u16,
+    /// The destination buffer width, in units of 64 pixels.
+    pub dbw: u8,
+    /// The storage mode of the pixel data.
+    pub psm: Psm,
+    /// The rectangle of the buffer to write.
+    pub rect: Rect,
+}
+
+impl Upload {
+    /// Describe a transfer into `rect` of the buffer at block `dbp`.
+    pub fn new(dbp: u16, dbw: u8, psm: Psm, rect: Rect) -> Self {
+        Upload {
+            dbp,
+            dbw,
+            psm,
+            rect,
+        }
+    }
+
+    /// Describe a transfer filling a `width` by `height` texture.
+    pub fn texture(texture: &Texture, width: u16, height: u16) -> Self {
+        Upload::new(
+            texture.tbp(),
+            buffer_width(width as u32, texture.psm()) as u8,
+            texture.psm(),
+            Rect::new(0, 0, width, height),
+        )
+    }
+
+    /// The number of quadwords of pixel data the transfer expects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel data does not fill a whole number of quadwords.
+    pub fn qwords(&self) -> usize {
+        image_qwords(self.rect, self.psm)
+    }
+
+    /// The register writes which set up the transfer, in the order they must be written.
+    pub fn registers(&self) -> (BITBLTBUF, TRXPOS, TRXREG, TRXDIR) {
+        (
+            BITBLTBUF::new()
+                .with_dbp(self.dbp)
+                .with_dbw(self.dbw)
+                .with_dpsm(self.psm as u8),
+            TRXPOS::new().with_dsax(self.rect.x).with_dsay(self.rect.y),
+            TRXREG::new()
+                .with_rrw(self.rect.width)
+                .with_rrh(self.rect.height),
+            TRXDIR::new().with_xdir(Direction::HostToLocal as u8),
+        )
+    }
+
+    /// Append the A+D writes which set up the transfer.
+    pub fn setup(&self, packet: &mut GifPacket) {
+        let (bitbltbuf, trxpos, trxreg, trxdir) = self.registers();
+        packet
+            .tag(GifTag::packed(4, &[Descriptor::AD]))
+            .register(bitbltbuf)
+            .register(trxpos)
+            .register(trxreg)
+            .register(trxdir);
+    }
+
+    /// Append the whole transfer, including its pixel data and a texture cache flush.
+    ///
+    /// This copies `pixels` into the packet, which suits small transfers such as CLUTs. `upload`
+    /// sends large images without copying them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` is not the size the transfer expects.
+    pub fn packet(&self, packet: &mut GifPacket, pixels: &[u128], eop: bool) {
+        assert_eq!(pixels.len(), self.qwords(), "Pixel data is the wrong size");
+        self.setup(packet);
+        packet.image(pixels, false);
+        packet
+            .tag(GifTag::packed(1, &[Descriptor::AD]).with_eop(eop))
+            .register(TEXFLUSH::new());
+    }
+}
+
+/// Upload `pixels` into GS local memory, waiting for the transfer to finish.
+///
+/// The pixel data is sent straight from `pixels` after a short setup packet, with an IMAGE GIFtag
+/// before every `MAX_NLOOP` quadwords, and is followed by a TEXFLUSH so that the new texels are
+/// used by later drawing. Each DMA transfer writes back the data cache lines it reads, so `pixels`
+/// needs no cache maintenance beforehand.
+///
+/// # Panics
+///
+/// Panics if `pixels` is not the size the transfer expects.
+pub fn upload<T>(gif: Gif, dest: &Upload, pixels: &mut Aligned<A16, [T]>) -> Gif {
+    let qwords = dest.qwords();
+    assert_eq!(
+        pixels.len() * mem::size_of::<T>(),
+        qwords * 16,
+        "Pixel data is the wrong size"
+    );
+
+    let mut header = Aligned([0u128; 6]);
+    let mut packet = GifPacket::new(&mut header);
+    dest.setup(&mut packet);
+    let mut gif = send(gif, packet.finish());
+
+    let start = pixels.as_mut_ptr() as *mut u128;
+    let mut offset = 0;
+    while offset < qwords {
+        let len = (qwords - offset).min(MAX_NLOOP as usize);
+
+        let mut tag = Aligned([GifTag::image(len as u16).to_qword()]);
+        gif = send(gif, &mut tag);
+
+        // Every quadword of the pixel data is 16-byte aligned, so each chunk is too.
+        let chunk = unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start.add(offset), len)
+                as *mut Aligned<A16, [u128]>)
+        };
+        gif = send(gif, chunk);
+
+        offset += len;
+    }
+
+    let mut trailer = Aligned([0u128; 2]);
+    let mut packet = GifPacket::new(&mut trailer);
+    packet
+        .tag(GifTag::packed(1, &[Descriptor::AD]).with_eop(true))
+        .register(TEXFLUSH::new());
+    send(gif, packet.finish())
+}
+
+/// The source of a local-to-host transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Download {
+    /// The source base pointer, in blocks.
+    pub sbp: u16,
+    /// The source buffer width, in units of 64 pixels.
+    pub sbw: u8,
+    /// The storage mode of the pixel data.
+    pub psm: Psm,
+    /// The rectangle of the buffer to read.
+    pub rect: Rect,
+}
+
+impl Download {
+    /// Describe a transfer out of `rect` of the buffer at block `sbp`.
+    pub fn new(sbp: u16, sbw: u8, psm: Psm, rect: Rect) -> Self {
+        Download {
+            sbp,
+            sbw,
+            psm,
+            rect,
+        }
+    }
+
+    /// Describe a transfer of the top-left `width` by `height` pixels of a framebuffer.
+    pub fn framebuffer(buffer: &FrameBuffer, width: u16, height: u16) -> Self {
+        Download::new(
+            buffer.fbp() * BLOCKS_PER_PAGE as u16,
+            buffer.fbw(),
+            buffer.psm(),
+            Rect::new(0, 0, width, height),
+        )
+    }
+
+    /// The number of quadwords of pixel data the transfer produces.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel data does not fill a whole number of quadwords.
+    pub fn qwords(&self) -> usize {
+        image_qwords(self.rect, self.psm)
+    }
+
+    /// The register writes which set up the transfer, in the order they must be written.
+    pub fn registers(&self) -> (BITBLTBUF, TRXPOS, TRXREG, TRXDIR) {
+        (
+            BITBLTBUF::new()
+                .with_sbp(self.sbp)
+                .with_sbw(self.sbw)
+                .with_spsm(self.psm as u8),
+            TRXPOS::new().with_ssax(self.rect.x).with_ssay(self.rect.y),
+            TRXREG::new()
+                .with_rrw(self.rect.width)
+                .with_rrh(self.rect.height),
+            TRXDIR::new().with_xdir(Direction::LocalToHost as u8),
+        )
+    }
+
+    /// Append the A+D writes which set up the transfer.
+    ///
+    /// A FINISH write comes before TRXDIR, so that `CSR.finish` is set once earlier drawing has
+    /// completed and the GS is ready to output the pixels.
+    pub fn setup(&self, packet: &mut GifPacket) {
+        let (bitbltbuf, trxpos, trxreg, trxdir) = self.registers();
+        packet
+            .tag(GifTag::packed(5, &[Descriptor::AD]).with_eop(true))
+            .register(bitbltbuf)
+            .register(trxpos)
+            .register(trxreg)
+            .register(FINISH::new())
+            .register(trxdir);
+    }
+}
+
+/// Read a rectangle of GS local memory into `pixels`, waiting for the transfer to finish.
+///
+/// The GS outputs the pixels through the VIF1 FIFO, so this needs both the GIF channel (to send the
+/// setup) and the VIF1 channel (to read the data). While the transfer runs the host bus is
+/// reversed with BUSDIR, and it is restored before returning.
+///
+/// The pixels are read in DMA transfers of at most 65535 quadwords each, which invalidate the data
+/// cache lines covering them, so `pixels` holds what the GS sent rather than stale cache lines.
+///
+/// # Panics
+///
+/// Panics if `pixels` is not the size the transfer produces.
+pub fn download_rect<T>(
+    gif: Gif,
+    mut vif1: Vif1,
+    src: &Download,
+    pixels: &mut Aligned<A16, [T]>,
+) -> (Gif, Vif1) {
+    let qwords = src.qwords();
+    assert_eq!(
+        pixels.len() * mem::size_of::<T>(),
+        qwords * 16,
+        "Pixel buffer is the wrong size"
+    );
+
+    // FINISH is latched until cleared by writing 1 to it.
+    CSR::new().with_finish(1).store();
+
+    let mut header = Aligned([0u128; 6]);
+    let mut packet = GifPacket::new(&mut header);
+    src.setup(&mut packet);
+    let gif = send(gif, packet.finish());
+
+    while CSR::load().finish() == 0 {}
+
+    unsafe { ptr::write_volatile(VIF1_STAT, VIF1_STAT_FDR) };
+    BUSDIR::new().with_dir(1).store();
+
+    let start = pixels.as_mut_ptr() as *mut u128;
+    for (offset, len) in dma_chunks(qwords) {
+        // Each transfer finishes before the next starts, so the borrow outlives it, and every
+        // quadword of the pixel data is 16-byte aligned, so each chunk is too.
+        let chunk: &'static mut Aligned<A16, [u128]> = unsafe {
+            &mut *(ptr::slice_from_raw_parts_mut(start.add(offset), len)
+                as *mut Aligned<A16, [u128]>)
+        };
+        vif1 = Transfer::to_mem(vif1, chunk).wait().0;
+    }
+
+    BUSDIR::new().store();
+    unsafe { ptr::write_volatile(VIF1_STAT, 0) };
+    CSR::new().with_finish(1).store();
+
+    (gif, vif1)
+}
+
+/// Split `qwords` quadwords into `(offset, len)` runs which each fit in one DMA transfer.
+fn dma_chunks(qwords: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..qwords)
+        .step_by(MAX_QWC)
+        .map(move |offset| (offset, (qwords - offset).min(MAX_QWC)))
+}
+
+/// Send `data` to the GIF and wait for it to be read.
+pub(crate) fn send(gif: Gif, data: &mut Aligned<A16, [u128]>) -> Gif {
+    // The transfer finishes before this returns, so the borrow outlives it.
+    let data: &'static mut Aligned<A16, [u128]> = unsafe { &mut *(data as *mut _) };
+    let (gif, _) = Transfer::from_mem(gif, data).wait();
+    gif
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_registers() {
+        let dest = Upload::new(0x2000, 2, Psm::PSMT8, Rect::new(16, 32, 128, 64));
+        let (bitbltbuf, trxpos, trxreg, trxdir) = dest.registers();
+
+        assert_eq!(u64::from(bitbltbuf), 0x1302_2000_0000_0000);
+        assert_eq!(u64::from(trxpos), (32 << 48) | (16 << 32));
+        assert_eq!(u64::from(trxreg), (64 << 32) | 128);
+        assert_eq!(u64::from(trxdir), 0);
+        assert_eq!(dest.qwords(), 128 * 64 / 16);
+    }
+
+    #[test]
+    fn packet_layout() {
+        let dest = Upload::new(0, 1, Psm::PSMCT32, Rect::new(0, 0, 4, 2));
+        let pixels = [0x1111u128, 0x2222];
+        let mut buffer = Aligned([0u128; 16]);
+        let mut packet = GifPacket::new(&mut buffer);
+        dest.packet(&mut packet, &pixels, true);
+        let packet: &[u128] = packet.finish();
+
+        // A+D setup, IMAGE tag, pixels, then TEXFLUSH.
+        assert_eq!(packet.len(), 5 + 1 + 2 + 2);
+        assert_eq!(packet[0], GifTag::packed(4, &[Descriptor::AD]).to_qword());
+        assert_eq!(packet[4] >> 64, 0x53);
+        assert_eq!(packet[5], GifTag::image(2).to_qword());
+        assert_eq!(&packet[6..8], &pixels);
+        assert_eq!(packet[9] >> 64, 0x3F);
+    }
+
+    #[test]
+    fn texture_destination() {
+        let mut vram = crate::vram::Allocator::new();
+        vram.alloc_texture(16, 16, Psm::PSMCT32).unwrap();
+        let texture = vram.alloc_texture(256, 256, Psm::PSMT4).unwrap();
+        let dest = Upload::texture(&texture, 256, 256);
+
+        assert_eq!((dest.dbp, dest.dbw), (4, 4));
+        assert_eq!(dest.qwords(), 256 * 256 / 32);
+    }
+
+    #[test]
+    fn download_registers() {
+        let mut vram = crate::vram::Allocator::new();
+        let _front = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+        let frame = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+        let src = Download::framebuffer(&frame, 640, 448);
+        let (bitbltbuf, trxpos, trxreg, trxdir) = src.registers();
+
+        assert_eq!(u64::from(bitbltbuf), (10 << 16) | (140 * 32));
+        assert_eq!(u64::from(trxpos), 0);
+        assert_eq!(u64::from(trxreg), (448 << 32) | 640);
+        assert_eq!(u64::from(trxdir), 1);
+        assert_eq!(src.qwords(), 640 * 448 / 4);
+    }
+
+    #[test]
+    fn download_finishes_before_trxdir() {
+        let src = Download::new(0, 1, Psm::PSMCT16, Rect::new(8, 8, 8, 8));
+        let mut buffer = Aligned([0u128; 8]);
+        let mut packet = GifPacket::new(&mut buffer);
+        src.setup(&mut packet);
+        let packet: &[u128] = packet.finish();
+
+        let addresses: [u128; 5] = core::array::from_fn(|i| packet[i + 1] >> 64);
+        assert_eq!(addresses, [0x50, 0x51, 0x52, 0x61, 0x53]);
+    }
+
+    #[test]
+    fn screenshot_is_split_into_dma_transfers() {
+        let src = Download::new(0, 10, Psm::PSMCT32, Rect::new(0, 0, 640, 448));
+        let mut chunks = dma_chunks(src.qwords());
+
+        assert_eq!(src.qwords(), 71_680);
+        assert_eq!(chunks.next(), Some((0, 0xFFFF)));
+        assert_eq!(chunks.next(), Some((0xFFFF, 71_680 - 0xFFFF)));
+        assert_eq!(chunks.next(), None);
+        assert_eq!(dma_chunks(0).next(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn partial_quadwords_are_rejected() {
+        Upload::new(0, 1, Psm::PSMCT32, Rect::new(0, 0, 3, 1)).qwords();
+    }
+}
@@ -0,0 +1,465 @@
+//! GS local memory allocation.
+//!
+//! The GS has 4 MiB of local memory, addressed in 32-bit words and divided into 2048-word pages of
+//! 32 64-word blocks. Different buffers have different alignment rules:
+//!
+//! - Framebuffers (FBP) and Z buffers (ZBP) must start on a page.
+//! - Textures (TBP) and CLUTs (CBP) must start on a block.
+//!
+//! Buffer widths (FBW/TBW) are in units of 64 pixels, and a buffer is laid out a page at a time,
+//! so its size depends on how many pages of its pixel storage mode it covers. `Allocator` tracks
+//! which blocks are in use and hands out typed handles for each kind of buffer.
+//!
+//! # Examples
+//!
+//! ```
+//! use prussia_gs::registers::Psm;
+//! use prussia_gs::vram::Allocator;
+//!
+//! let mut vram = Allocator::new();
+//! let front = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+//! let back = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+//! let depth = vram.alloc_zbuffer(640, 448, Psm::PSMZ24).unwrap();
+//! assert_eq!((front.fbp(), back.fbp(), depth.zbp()), (0, 140, 280));
+//! ```
+
+use crate::registers::Psm;
+
+/// The number of words in GS local memory.
+pub const WORDS: u32 = 1 << 20;
+/// The number of words in a page.
+pub const PAGE_WORDS: u32 = 2048;
+/// The number of words in a block.
+pub const BLOCK_WORDS: u32 = 64;
+/// The number of blocks in a page.
+pub const BLOCKS_PER_PAGE: u32 = PAGE_WORDS / BLOCK_WORDS;
+
+const BLOCKS: usize = (WORDS / BLOCK_WORDS) as usize;
+
+/// The order of blocks within a page, indexed by block row and column.
+const BLOCKS_32: [[u8; 8]; 4] = [
+    [0, 1, 4, 5, 16, 17, 20, 21],
+    [2, 3, 6, 7, 18, 19, 22, 23],
+    [8, 9, 12, 13, 24, 25, 28, 29],
+    [10, 11, 14, 15, 26, 27, 30, 31],
+];
+const BLOCKS_Z32: [[u8; 8]; 4] = [
+    [24, 25, 28, 29, 8, 9, 12, 13],
+    [26, 27, 30, 31, 10, 11, 14, 15],
+    [16, 17, 20, 21, 0, 1, 4, 5],
+    [18, 19, 22, 23, 2, 3, 6, 7],
+];
+const BLOCKS_16: [[u8; 4]; 8] = [
+    [0, 2, 8, 10],
+    [1, 3, 9, 11],
+    [4, 6, 12, 14],
+    [5, 7, 13, 15],
+    [16, 18, 24, 26],
+    [17, 19, 25, 27],
+    [20, 22, 28, 30],
+    [21, 23, 29, 31],
+];
+const BLOCKS_16S: [[u8; 4]; 8] = [
+    [0, 2, 16, 18],
+    [1, 3, 17, 19],
+    [8, 10, 24, 26],
+    [9, 11, 25, 27],
+    [4, 6, 20, 22],
+    [5, 7, 21, 23],
+    [12, 14, 28, 30],
+    [13, 15, 29, 31],
+];
+const BLOCKS_Z16: [[u8; 4]; 8] = [
+    [24, 26, 16, 18],
+    [25, 27, 17, 19],
+    [28, 30, 20, 22],
+    [29, 31, 21, 23],
+    [8, 10, 0, 2],
+    [9, 11, 1, 3],
+    [12, 14, 4, 6],
+    [13, 15, 5, 7],
+];
+const BLOCKS_Z16S: [[u8; 4]; 8] = [
+    [24, 26, 8, 10],
+    [25, 27, 9, 11],
+    [16, 18, 0, 2],
+    [17, 19, 1, 3],
+    [28, 30, 12, 14],
+    [29, 31, 13, 15],
+    [20, 22, 4, 6],
+    [21, 23, 5, 7],
+];
+
+/// The block number within a page of the block at block column `x` and block row `y`.
+pub(crate) fn block_in_page(psm: Psm, x: u32, y: u32) -> u32 {
+    let (x, y) = (x as usize, y as usize);
+    let block = match psm {
+        Psm::PSMZ32 | Psm::PSMZ24 => BLOCKS_Z32[y][x],
+        Psm::PSMCT16 | Psm::PSMT4 => BLOCKS_16[y][x],
+        Psm::PSMCT16S => BLOCKS_16S[y][x],
+        Psm::PSMZ16 => BLOCKS_Z16[y][x],
+        Psm::PSMZ16S => BLOCKS_Z16S[y][x],
+        _ => BLOCKS_32[y][x],
+    };
+    block as u32
+}
+
+/// The buffer width of a `width`-pixel wide buffer, in units of 64 pixels.
+///
+/// PSMT8 and PSMT4 pages are 128 pixels wide, so their buffer widths are always even.
+pub fn buffer_width(width: u32, psm: Psm) -> u32 {
+    let (page_width, _) = psm.page_size();
+    width.div_ceil(page_width) * (page_width / 64)
+}
+
+/// The number of blocks a `width` by `height` buffer occupies.
+///
+/// A buffer which fits inside a single page only needs the blocks up to the last one it
+/// touches; larger buffers occupy whole pages.
+pub fn size_in_blocks(width: u32, height: u32, psm: Psm) -> u32 {
+    let (page_width, page_height) = psm.page_size();
+    let (block_width, block_height) = psm.block_size();
+
+    if width <= page_width && height <= page_height {
+        let columns = width.div_ceil(block_width);
+        let rows = height.div_ceil(block_height);
+        let last = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| block_in_page(psm, x, y)))
+            .max()
+            .unwrap_or(0);
+        last + 1
+    } else {
+        width.div_ceil(page_width) * height.div_ceil(page_height) * BLOCKS_PER_PAGE
+    }
+}
+
+/// A range of allocated blocks.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Allocation {
+    block: u16,
+    blocks: u16,
+}
+
+impl Allocation {
+    /// The first block of the allocation.
+    pub fn block(&self) -> u32 {
+        self.block as u32
+    }
+
+    /// The number of blocks allocated.
+    pub fn blocks(&self) -> u32 {
+        self.blocks as u32
+    }
+
+    /// The word address of the start of the allocation.
+    pub fn address(&self) -> u32 {
+        self.block() * BLOCK_WORDS
+    }
+}
+
+/// A page-aligned framebuffer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameBuffer {
+    alloc: Allocation,
+    width: u32,
+    psm: Psm,
+}
+
+impl FrameBuffer {
+    /// The framebuffer base pointer, in pages, as used by FRAME and DISPFB.
+    pub fn fbp(&self) -> u16 {
+        (self.alloc.block() / BLOCKS_PER_PAGE) as u16
+    }
+
+    /// The framebuffer width, in units of 64 pixels.
+    pub fn fbw(&self) -> u8 {
+        buffer_width(self.width, self.psm) as u8
+    }
+
+    /// The pixel storage mode of the framebuffer.
+    pub fn psm(&self) -> Psm {
+        self.psm
+    }
+}
+
+/// A page-aligned Z buffer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ZBuffer {
+    alloc: Allocation,
+    psm: Psm,
+}
+
+impl ZBuffer {
+    /// The Z buffer base pointer, in pages, as used by ZBUF.
+    pub fn zbp(&self) -> u16 {
+        (self.alloc.block() / BLOCKS_PER_PAGE) as u16
+    }
+
+    /// The pixel storage mode of the Z buffer.
+    pub fn psm(&self) -> Psm {
+        self.psm
+    }
+}
+
+/// A block-aligned texture.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Texture {
+    alloc: Allocation,
+    width: u32,
+    psm: Psm,
+}
+
+impl Texture {
+    /// The texture base pointer, in blocks, as used by TEX0 and BITBLTBUF.
+    pub fn tbp(&self) -> u16 {
+        self.alloc.block() as u16
+    }
+
+    /// The texture buffer width, in units of 64 pixels.
+    pub fn tbw(&self) -> u8 {
+        buffer_width(self.width, self.psm) as u8
+    }
+
+    /// The pixel storage mode of the texture.
+    pub fn psm(&self) -> Psm {
+        self.psm
+    }
+}
+
+impl From<FrameBuffer> for Allocation {
+    fn from(buffer: FrameBuffer) -> Self {
+        buffer.alloc
+    }
+}
+
+impl From<ZBuffer> for Allocation {
+    fn from(buffer: ZBuffer) -> Self {
+        buffer.alloc
+    }
+}
+
+impl From<Texture> for Allocation {
+    fn from(texture: Texture) -> Self {
+        texture.alloc
+    }
+}
+
+/// A summary of free GS memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Usage {
+    /// The number of free blocks.
+    pub free_blocks: u32,
+    /// The number of blocks in the largest run of free blocks.
+    pub largest_free_blocks: u32,
+    /// The number of separate runs of free blocks.
+    pub free_runs: u32,
+}
+
+impl Usage {
+    /// The percentage of free memory which is outside the largest free run, and so unusable for
+    /// an allocation the size of all free memory.
+    pub fn fragmentation_percent(&self) -> u32 {
+        (self.largest_free_blocks * 100)
+            .checked_div(self.free_blocks)
+            .map_or(0, |contiguous| 100 - contiguous)
+    }
+}
+
+/// A GS local memory allocator.
+pub struct Allocator {
+    /// One bit per block, set if the block is allocated.
+    used: [u64; BLOCKS / 64],
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Allocator::new()
+    }
+}
+
+impl Allocator {
+    /// Create an allocator with all of GS memory free.
+    pub const fn new() -> Self {
+        Allocator {
+            used: [0; BLOCKS / 64],
+        }
+    }
+
+    /// Allocate a page-aligned framebuffer.
+    ///
+    /// Returns `None` if there is no room.
+    pub fn alloc_framebuffer(&mut self, width: u32, height: u32, psm: Psm) -> Option<FrameBuffer> {
+        let (page_width, page_height) = psm.page_size();
+        let pages = width.div_ceil(page_width) * height.div_ceil(page_height);
+        let alloc = self.alloc(pages * BLOCKS_PER_PAGE, BLOCKS_PER_PAGE)?;
+        Some(FrameBuffer { alloc, width, psm })
+    }
+
+    /// Allocate a page-aligned Z buffer.
+    ///
+    /// Returns `None` if there is no room.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `psm` is not a depth format.
+    pub fn alloc_zbuffer(&mut self, width: u32, height: u32, psm: Psm) -> Option<ZBuffer> {
+        assert!(psm.is_depth(), "{:?} is not a depth format", psm);
+        let (page_width, page_height) = psm.page_size();
+        let pages = width.div_ceil(page_width) * height.div_ceil(page_height);
+        let alloc = self.alloc(pages * BLOCKS_PER_PAGE, BLOCKS_PER_PAGE)?;
+        Some(ZBuffer { alloc, psm })
+    }
+
+    /// Allocate a block-aligned texture.
+    ///
+    /// Returns `None` if there is no room.
+    pub fn alloc_texture(&mut self, width: u32, height: u32, psm: Psm) -> Option<Texture> {
+        let alloc = self.alloc(size_in_blocks(width, height, psm), 1)?;
+        Some(Texture { alloc, width, psm })
+    }
+
+    /// Allocate `blocks` blocks, starting on a multiple of `alignment` blocks.
+    ///
+    /// Returns `None` if there is no room.
+    pub fn alloc(&mut self, blocks: u32, alignment: u32) -> Option<Allocation> {
+        let blocks = blocks as usize;
+        let alignment = alignment.max(1) as usize;
+        if blocks == 0 || blocks > BLOCKS {
+            return None;
+        }
+
+        let mut start = 0;
+        while start + blocks <= BLOCKS {
+            match (start..start + blocks).find(|&block| self.is_used(block)) {
+                // Skip past the used block to the next aligned candidate.
+                Some(used) => start = (used + 1).next_multiple_of(alignment),
+                None => {
+                    self.mark(start, blocks, true);
+                    return Some(Allocation {
+                        block: start as u16,
+                        blocks: blocks as u16,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Free a buffer, making its memory available again.
+    pub fn free<A: Into<Allocation>>(&mut self, allocation: A) {
+        let allocation = allocation.into();
+        self.mark(allocation.block as usize, allocation.blocks as usize, false);
+    }
+
+    /// Summarise how much memory is free, and how fragmented it is.
+    pub fn usage(&self) -> Usage {
+        let mut usage = Usage {
+            free_blocks: 0,
+            largest_free_blocks: 0,
+            free_runs: 0,
+        };
+        let mut run = 0;
+
+        for block in 0..BLOCKS {
+            if self.is_used(block) {
+                run = 0;
+            } else {
+                if run == 0 {
+                    usage.free_runs += 1;
+                }
+                run += 1;
+                usage.free_blocks += 1;
+                usage.largest_free_blocks = usage.largest_free_blocks.max(run);
+            }
+        }
+
+        usage
+    }
+
+    fn is_used(&self, block: usize) -> bool {
+        self.used[block / 64] & (1 << (block % 64)) != 0
+    }
+
+    fn mark(&mut self, start: usize, blocks: usize, used: bool) {
+        for block in start..start + blocks {
+            if used {
+                self.used[block / 64] |= 1 << (block % 64);
+            } else {
+                self.used[block / 64] &= !(1 << (block % 64));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_sizes() {
+        assert_eq!(size_in_blocks(8, 8, Psm::PSMCT32), 1);
+        assert_eq!(size_in_blocks(16, 16, Psm::PSMCT32), 4);
+        assert_eq!(size_in_blocks(32, 32, Psm::PSMCT32), 16);
+        assert_eq!(size_in_blocks(64, 32, Psm::PSMCT32), 32);
+        assert_eq!(size_in_blocks(16, 16, Psm::PSMT4), 1);
+        assert_eq!(size_in_blocks(64, 64, Psm::PSMT8), 16);
+        assert_eq!(size_in_blocks(256, 256, Psm::PSMT8), 8 * 32);
+        // The Z formats number their blocks differently.
+        assert_eq!(size_in_blocks(8, 8, Psm::PSMZ32), 25);
+    }
+
+    #[test]
+    fn buffer_widths() {
+        assert_eq!(buffer_width(640, Psm::PSMCT32), 10);
+        assert_eq!(buffer_width(100, Psm::PSMCT16), 2);
+        assert_eq!(buffer_width(64, Psm::PSMT8), 2);
+        assert_eq!(buffer_width(256, Psm::PSMT4), 4);
+    }
+
+    #[test]
+    fn framebuffers_are_page_aligned() {
+        let mut vram = Allocator::new();
+        let texture = vram.alloc_texture(16, 16, Psm::PSMCT32).unwrap();
+        let frame = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+        let small = vram.alloc_texture(16, 16, Psm::PSMCT32).unwrap();
+
+        assert_eq!(texture.tbp(), 0);
+        assert_eq!(frame.fbp(), 1);
+        assert_eq!(frame.fbw(), 10);
+        // Textures are only block aligned, so fill the gap before the framebuffer.
+        assert_eq!(small.tbp(), 4);
+    }
+
+    #[test]
+    fn free_and_reuse() {
+        let mut vram = Allocator::new();
+        let a = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+        let b = vram.alloc_framebuffer(640, 448, Psm::PSMCT32).unwrap();
+        assert_eq!(b.fbp(), 140);
+
+        vram.free(a);
+        let usage = vram.usage();
+        assert_eq!(usage.free_blocks, (512 - 140) * 32);
+        assert_eq!(usage.free_runs, 2);
+        assert_eq!(usage.largest_free_blocks, (512 - 280) * 32);
+
+        let c = vram.alloc_zbuffer(640, 448, Psm::PSMZ16).unwrap();
+        assert_eq!(c.zbp(), 0);
+    }
+
+    #[test]
+    fn exhaustion_and_fragmentation() {
+        let mut vram = Allocator::new();
+        assert!(vram.alloc_framebuffer(1024, 1024, Psm::PSMCT32).is_some());
+        assert!(vram.alloc_texture(8, 8, Psm::PSMCT32).is_none());
+        assert_eq!(vram.usage().fragmentation_percent(), 0);
+
+        let mut vram = Allocator::new();
+        let low = vram.alloc(BLOCKS as u32 / 4, 1).unwrap();
+        let _high = vram.alloc(BLOCKS as u32 / 4, 1).unwrap();
+        vram.free(low);
+        assert_eq!(vram.usage().fragmentation_percent(), 34);
+        assert!(vram.alloc(BLOCKS as u32 * 3 / 4, 1).is_none());
+    }
+}
@@ -0,0 +1,122 @@
+//! EE cache maintenance.
+//!
+//! The EE has an 8KiB two-way data cache and a 16KiB two-way instruction cache, both with 64-byte
+//! lines. DMA transfers read and write main memory directly, so data written through the cache
+//! must be written back before a transfer reads it, and stale lines must be invalidated before the
+//! CPU reads what a transfer wrote. Likewise, the instruction cache must be invalidated after
+//! writing code to memory.
+//!
+//! The range functions operate on every line overlapping the range. Invalidating without writing
+//! back discards any other data sharing the first and last lines, so buffers received by DMA are
+//! best aligned to `LINE_SIZE`.
+
+use core::arch::asm;
+
+/// The size of a cache line in bytes.
+pub const LINE_SIZE: usize = 64;
+
+/// The number of sets in the data cache.
+const DATA_SETS: usize = 64;
+
+/// The number of sets in the instruction cache.
+const INSTRUCTION_SETS: usize = 128;
+
+/// An unmapped, cached address, used as a base for index operations.
+const KSEG0: usize = 0x8000_0000;
+
+// The CACHE instruction is MIPS III, and the EE wants the pipeline synchronised around data
+// cache operations.
+macro_rules! cache_ops {
+    ($($(#[$attr:meta])* $name:ident = $op:literal;)*) => {
+        $(
+            $(#[$attr])*
+            #[inline(always)]
+            fn $name(addr: usize) {
+                unsafe {
+                    asm!(
+                        ".set push",
+                        ".set mips3",
+                        "sync",
+                        concat!("cache ", $op, ", 0({0})"),
+                        "sync",
+                        ".set pop",
+                        in(reg) addr,
+                    )
+                };
+            }
+        )*
+    };
+}
+
+cache_ops! {
+    /// Write back and invalidate a data cache line by index and way.
+    dxwbin = "0x14";
+    /// Invalidate a data cache line by index and way.
+    dxin = "0x16";
+    /// Write back and invalidate the data cache line holding an address.
+    dhwbin = "0x18";
+    /// Invalidate the data cache line holding an address.
+    dhin = "0x1A";
+    /// Write back the data cache line holding an address, keeping it cached.
+    dhwoin = "0x1C";
+    /// Invalidate an instruction cache line by index and way.
+    ixin = "0x07";
+    /// Invalidate the instruction cache line holding an address.
+    ihin = "0x0B";
+}
+
+/// Call `op` with the address of each cache line overlapping `len` bytes from `addr`.
+fn for_each_line<F: FnMut(usize)>(addr: usize, len: usize, mut op: F) {
+    if len == 0 {
+        return;
+    }
+    let start = addr & !(LINE_SIZE - 1);
+    let end = addr + len;
+    for line in (start..end).step_by(LINE_SIZE) {
+        op(line);
+    }
+}
+
+/// Call `op` with the index address of each line in both ways of a cache with `sets` sets.
+fn for_each_index<F: FnMut(usize)>(sets: usize, mut op: F) {
+    for set in 0..sets {
+        // Bit 0 of an index address selects the way.
+        op(KSEG0 + set * LINE_SIZE);
+        op(KSEG0 + set * LINE_SIZE + 1);
+    }
+}
+
+/// Write back any dirty data cache lines overlapping `len` bytes from `addr`, keeping them cached.
+pub fn writeback_data(addr: usize, len: usize) {
+    for_each_line(addr, len, dhwoin);
+}
+
+/// Write back and invalidate the data cache lines overlapping `len` bytes from `addr`.
+pub fn writeback_invalidate_data(addr: usize, len: usize) {
+    for_each_line(addr, len, dhwbin);
+}
+
+/// Invalidate the data cache lines overlapping `len` bytes from `addr`, discarding dirty data.
+pub fn invalidate_data(addr: usize, len: usize) {
+    for_each_line(addr, len, dhin);
+}
+
+/// Write back and invalidate the whole data cache.
+pub fn writeback_invalidate_data_all() {
+    for_each_index(DATA_SETS, dxwbin);
+}
+
+/// Invalidate the whole data cache, discarding dirty data.
+pub fn invalidate_data_all() {
+    for_each_index(DATA_SETS, dxin);
+}
+
+/// Invalidate the instruction cache lines overlapping `len` bytes from `addr`.
+pub fn invalidate_instruction(addr: usize, len: usize) {
+    for_each_line(addr, len, ihin);
+}
+
+/// Invalidate the whole instruction cache.
+pub fn invalidate_instruction_all() {
+    for_each_index(INSTRUCTION_SETS, ixin);
+}
@@ -13,6 +13,8 @@
 #![feature(asm_experimental_arch)]
 
 pub mod atomic;
+pub mod cache;
+pub mod uncached;
 pub mod cop0;
 pub mod interrupts;
 
@@ -0,0 +1,149 @@
+//! Uncached and uncached accelerated views of main memory.
+//!
+//! Besides the usual cached addresses, the EE can reach main memory through an uncached alias at
+//! 0x2000_0000 and an uncached accelerated alias at 0x3000_0000. Writes through either go straight
+//! to memory, so a DMA packet built through them needs no cache maintenance before it is sent. The
+//! accelerated alias also gathers sequential writes in the uncached accelerated buffer (UCAB),
+//! which makes it the fastest way to fill a packet, but it should only be written to.
+//!
+//! `uncached` reborrows a buffer through the uncached alias, so any code which fills a buffer,
+//! such as a packet builder, can write to it uncached. `UcabWriter` wraps the accelerated alias
+//! and only allows writes. Scratchpad RAM has no such aliases, so neither accepts buffers in it.
+
+use core::arch::asm;
+use core::marker::PhantomData;
+use core::mem;
+
+use crate::cache;
+
+/// The base of the uncached alias of main memory.
+pub const UNCACHED: usize = 0x2000_0000;
+
+/// The base of the uncached accelerated alias of main memory.
+pub const UNCACHED_ACCELERATED: usize = 0x3000_0000;
+
+/// The bits of an address which select a location in main memory.
+const PHYSICAL_MASK: usize = 0x0FFF_FFFF;
+
+/// The address of scratchpad RAM, and its size in bytes.
+const SCRATCHPAD: (usize, usize) = (0x7000_0000, 16 * 1024);
+
+/// The cached address of `addr` in main memory.
+pub fn cached_address(addr: usize) -> usize {
+    addr & PHYSICAL_MASK
+}
+
+/// The uncached alias of `addr` in main memory.
+pub fn uncached_address(addr: usize) -> usize {
+    (addr & PHYSICAL_MASK) | UNCACHED
+}
+
+/// The uncached accelerated alias of `addr` in main memory.
+pub fn accelerated_address(addr: usize) -> usize {
+    (addr & PHYSICAL_MASK) | UNCACHED_ACCELERATED
+}
+
+/// Write back and invalidate the data cache lines covering `buffer`, so no dirty line can later
+/// be written back over data written through an alias, and no stale line hides it.
+///
+/// # Panics
+///
+/// Panics if `buffer` is in scratchpad RAM.
+fn evict<T: ?Sized>(buffer: &T) {
+    let addr = buffer as *const T as *const u8 as usize;
+    let (base, size) = SCRATCHPAD;
+    assert!(
+        !(base..base + size).contains(&addr),
+        "Buffer at {:#x} is in scratchpad RAM, which has no uncached alias",
+        addr
+    );
+    cache::writeback_invalidate_data(addr, mem::size_of_val(buffer));
+}
+
+/// Reborrow `buffer` in main memory through its uncached alias.
+///
+/// The cache lines covering `buffer` are written back and invalidated first. Lines shared with
+/// other data may be cached again if that data is used while the uncached borrow is alive.
+///
+/// # Panics
+///
+/// Panics if `buffer` is in scratchpad RAM.
+pub fn uncached<T: ?Sized>(buffer: &mut T) -> &mut T {
+    evict(buffer);
+    let ptr = buffer as *mut T;
+    let addr = ptr as *mut u8 as usize;
+    // The alias refers to the same memory, which is exclusively borrowed for the same lifetime.
+    unsafe { &mut *ptr.wrapping_byte_add(uncached_address(addr).wrapping_sub(addr)) }
+}
+
+/// A write-only view of a buffer in main memory through its uncached accelerated alias.
+///
+/// Writes are gathered in the UCAB and flushed to memory when the writer is dropped, after which
+/// the buffer is ready to be sent by DMA.
+pub struct UcabWriter<'a, T: Copy> {
+    ptr: *mut T,
+    len: usize,
+    _buffer: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: Copy> UcabWriter<'a, T> {
+    /// Borrow `buffer` for writing through the uncached accelerated alias.
+    ///
+    /// The cache lines covering `buffer` are written back and invalidated first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is in scratchpad RAM.
+    pub fn new(buffer: &'a mut [T]) -> Self {
+        evict(buffer);
+        UcabWriter {
+            ptr: accelerated_address(buffer.as_mut_ptr() as usize) as *mut T,
+            len: buffer.len(),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// The number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Write `value` to element `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn write(&mut self, index: usize, value: T) {
+        assert!(index < self.len, "Index {} out of bounds", index);
+        unsafe { self.ptr.add(index).write_volatile(value) }
+    }
+
+    /// Write `values` to the elements starting at `offset`, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the elements are out of bounds.
+    pub fn write_slice(&mut self, offset: usize, values: &[T]) {
+        assert!(
+            offset <= self.len && values.len() <= self.len - offset,
+            "Elements {}..{} out of bounds",
+            offset,
+            offset + values.len()
+        );
+        for (i, &value) in values.iter().enumerate() {
+            unsafe { self.ptr.add(offset + i).write_volatile(value) }
+        }
+    }
+}
+
+impl<'a, T: Copy> Drop for UcabWriter<'a, T> {
+    fn drop(&mut self) {
+        // Wait for the UCAB to drain into memory.
+        unsafe { asm!("sync") };
+    }
+}
@@ -0,0 +1,232 @@
+//! Double buffering of VU1 data memory.
+//!
+//! VIF1 can split VU1 data memory into two buffers, at BASE and BASE + OFFSET quadwords. An
+//! UNPACK with its FLG bit set is written relative to TOPS, the buffer being filled, and each
+//! MSCAL, MSCALF or MSCNT hands that buffer to the microprogram as TOP (read with XTOP) before
+//! switching TOPS to the other buffer. So while VU1 processes one batch, the next is unpacked into
+//! the other buffer.
+//!
+//! `DoubleBuffer` appends the codes for this to a `VifPacket`: `unpack` writes into the buffer
+//! being filled, and `kick` starts the microprogram on it. The first kick uses MSCAL; later kicks
+//! use MSCNT while the last call through the `MicroMemory` was to the same program and entry
+//! point, so the microprogram should end each batch with an [E] bit and then branch back to wait
+//! for the next one.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::vifcode::{UnpackFormat, VifPacket};
+//! use prussia_vu::buffer::DoubleBuffer;
+//! use prussia_vu::memory::MicroMemory;
+//! use prussia_vu::program::MicroProgram;
+//!
+//! fn draw(batches: &[&[u32]], memory: &mut MicroMemory, program: &MicroProgram) {
+//!     static mut PACKET: Aligned<A16, [u128; 4096]> = Aligned([0; 4096]);
+//!
+//!     let mut packet = VifPacket::new(unsafe { &mut PACKET });
+//!     let mut buffer = DoubleBuffer::new(&mut packet, 8, 496);
+//!     for batch in batches {
+//!         buffer.unpack(&mut packet, UnpackFormat::V4_32, 0, batch);
+//!         // Tell the microprogram how many vertices there are.
+//!         buffer.kick(&mut packet, memory, program, "main", batch.len() as u16 / 4);
+//!     }
+//! }
+//! ```
+
+use prussia_dma::vifcode::{UnpackFormat, VifCode, VifPacket};
+
+use crate::memory::MicroMemory;
+use crate::program::MicroProgram;
+
+/// The number of quadwords in VU1 data memory (16 KiB).
+pub const VU1_DATA_SIZE: usize = 1024;
+
+/// A pair of buffers in VU1 data memory, filled and processed in turn.
+pub struct DoubleBuffer {
+    base: u16,
+    size: u16,
+    /// VIF1 STAT.DBF: whether TOPS is the second buffer.
+    dbf: bool,
+    /// Whether a kick has started a microprogram yet.
+    started: bool,
+}
+
+impl DoubleBuffer {
+    /// Split VU1 data memory into two buffers of `size` quadwords from `base`, appending the BASE
+    /// and OFFSET codes to `packet`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero, or the buffers do not fit in VU1 data memory.
+    pub fn new(packet: &mut VifPacket, base: u16, size: u16) -> Self {
+        assert!(size > 0, "Double buffers must not be empty");
+        assert!(
+            base as usize + 2 * size as usize <= VU1_DATA_SIZE,
+            "Double buffers of {} quadwords from {} do not fit in VU1 data memory",
+            size,
+            base
+        );
+        // OFFSET resets TOPS to BASE, so BASE must come first.
+        packet.base(base).offset(size);
+        DoubleBuffer {
+            base,
+            size,
+            dbf: false,
+            started: false,
+        }
+    }
+
+    /// The number of quadwords in each buffer.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// The address of the buffer being filled (TOPS).
+    pub fn tops(&self) -> u16 {
+        if self.dbf {
+            self.base + self.size
+        } else {
+            self.base
+        }
+    }
+
+    /// Append an UNPACK of `data`, in `format`, to quadword `addr` of the buffer being filled.
+    /// The number of vectors is worked out from the length of `data`, assuming a STCYCL with
+    /// `cl >= wl`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not hold a whole number of vectors, up to `MAX_UNPACK`, or they do
+    /// not fit in the buffer from `addr`.
+    pub fn unpack(&self, packet: &mut VifPacket, format: UnpackFormat, addr: u16, data: &[u32]) {
+        let num = format.vectors(data.len());
+        assert!(
+            addr as usize + num <= self.size as usize,
+            "UNPACK of {} vectors to {} overflows the buffer",
+            num,
+            addr
+        );
+        packet.unpack_code(VifCode::unpack(format, num, addr).with_tops(true), data);
+    }
+
+    /// Hand the buffer being filled to `program`, appending an ITOP of `itop` (read with XITOP)
+    /// and then either an MSCAL at `entry`, uploading `program` if needed, or an MSCNT.
+    ///
+    /// MSCNT is used after the first kick if the last call through `memory` started `program` at
+    /// `entry` and it is still resident. It continues from the [E] bit which ended the last batch
+    /// rather than from `entry`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `program` has no entry point called `entry`, or is larger than micro memory.
+    pub fn kick(
+        &mut self,
+        packet: &mut VifPacket,
+        memory: &mut MicroMemory,
+        program: &MicroProgram,
+        entry: &str,
+        itop: u16,
+    ) {
+        packet.itop(itop);
+        let address = memory
+            .address(program)
+            .zip(program.entry(entry))
+            .map(|(start, offset)| start + offset);
+        let continues = self.started
+            && memory.last_call().is_some_and(|(called, called_address)| {
+                called.same_code(program) && Some(called_address) == address
+            });
+        if continues {
+            packet.mscnt();
+        } else {
+            memory.call(packet, program, entry);
+            self.started = true;
+        }
+        self.dbf = !self.dbf;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aligned::Aligned;
+
+    static CODE: [u64; 4] = [0; 4];
+
+    #[test]
+    fn batches_alternate_buffers() {
+        let program = MicroProgram::new("test", &CODE, &[("main", 2)]);
+        let mut memory = MicroMemory::vu1();
+        memory.upload(&mut VifPacket::new(&mut Aligned([0u128; 4])), &program);
+
+        let mut buffer = Aligned([0u128; 4]);
+        let mut packet = VifPacket::new(&mut buffer);
+        let mut double = DoubleBuffer::new(&mut packet, 0, 512);
+        assert_eq!(double.tops(), 0);
+        double.unpack(&mut packet, UnpackFormat::S_32, 8, &[1]);
+        double.kick(&mut packet, &mut memory, &program, "main", 1);
+        assert_eq!(double.tops(), 512);
+        double.kick(&mut packet, &mut memory, &program, "main", 0);
+        assert_eq!(double.tops(), 0);
+        let packet: &[u128] = packet.finish();
+
+        let words: [u32; 8] = [
+            VifCode::base(0).into(),
+            VifCode::offset(512).into(),
+            VifCode::unpack(UnpackFormat::S_32, 1, 8)
+                .with_tops(true)
+                .into(),
+            1,
+            VifCode::itop(1).into(),
+            VifCode::mscal(2).into(),
+            VifCode::itop(0).into(),
+            VifCode::mscnt().into(),
+        ];
+        for (i, &word) in words.iter().enumerate() {
+            assert_eq!((packet[i / 4] >> (i % 4 * 32)) as u32, word);
+        }
+    }
+
+    #[test]
+    fn other_calls_restart_the_program() {
+        let program = MicroProgram::new("test", &CODE, &[("main", 2), ("clip", 3)]);
+        let mut memory = MicroMemory::vu1();
+        memory.upload(&mut VifPacket::new(&mut Aligned([0u128; 4])), &program);
+
+        let mut buffer = Aligned([0u128; 4]);
+        let mut packet = VifPacket::new(&mut buffer);
+        let mut double = DoubleBuffer::new(&mut packet, 0, 512);
+        double.kick(&mut packet, &mut memory, &program, "main", 0);
+        // Another entry point is started afresh.
+        double.kick(&mut packet, &mut memory, &program, "clip", 0);
+        memory.call(&mut packet, &program, "main");
+        // The kick is not what started the program last.
+        double.kick(&mut packet, &mut memory, &program, "clip", 0);
+        let packet: &[u128] = packet.finish();
+
+        let words: [u32; 9] = [
+            VifCode::base(0).into(),
+            VifCode::offset(512).into(),
+            VifCode::itop(0).into(),
+            VifCode::mscal(2).into(),
+            VifCode::itop(0).into(),
+            VifCode::mscal(3).into(),
+            VifCode::mscal(2).into(),
+            VifCode::itop(0).into(),
+            VifCode::mscal(3).into(),
+        ];
+        for (i, &word) in words.iter().enumerate() {
+            assert_eq!((packet[i / 4] >> (i % 4 * 32)) as u32, word);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn unpack_overflows_buffer() {
+        let mut buffer = Aligned([0u128; 4]);
+        let mut packet = VifPacket::new(&mut buffer);
+        let double = DoubleBuffer::new(&mut packet, 0, 1);
+        double.unpack(&mut packet, UnpackFormat::S_32, 0, &[1, 2]);
+    }
+}
@@ -0,0 +1,37 @@
+//! Routines for the PlayStation 2 Vector Units.
+//!
+//! VU1 runs microprograms from its 16 KiB of micro memory, which are uploaded and started through
+//! VIF1. A `program::MicroProgram` is microcode embedded in the executable along with the offsets
+//! of its entry points, and `memory::MicroMemory` keeps track of which programs are resident, so
+//! that each is only uploaded when it is not already there. Uploads and calls are appended to a
+//! `prussia_dma::vifcode::VifPacket`. `buffer::DoubleBuffer` splits VU1 data memory in two, so
+//! that the next batch of data is unpacked while the microprogram works on the last.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aligned::{Aligned, A16};
+//! use prussia_dma::vifcode::VifPacket;
+//! use prussia_vu::memory::MicroMemory;
+//! use prussia_vu::program::MicroProgram;
+//!
+//! fn draw(vif1: prussia_dma::Vif1, memory: &mut MicroMemory) -> prussia_dma::Vif1 {
+//!     static mut PACKET: Aligned<A16, [u128; 1024]> = Aligned([0; 1024]);
+//!
+//!     // NOP[E] / NOP, then NOP / NOP for the branch delay slot.
+//!     static CODE: [u64; 2] = [0x4000_02FF_8000_033C, 0x0000_02FF_8000_033C];
+//!     let program = MicroProgram::new("nothing", &CODE, &[("main", 0)]);
+//!
+//!     let mut packet = VifPacket::new(unsafe { &mut PACKET });
+//!     memory.call(&mut packet, &program, "main");
+//!     let (vif1, _) = prussia_dma::Transfer::from_mem(vif1, packet.finish()).wait();
+//!     vif1
+//! }
+//! ```
+
+#![no_std]
+#![deny(missing_docs)]
+
+pub mod buffer;
+pub mod memory;
+pub mod program;
@@ -0,0 +1,291 @@
+//! Micro memory residency.
+//!
+//! Uploading a microprogram costs a DMA transfer of its whole length, so programs are left in
+//! micro memory between calls. `MicroMemory` remembers where each uploaded program is, uploads a
+//! program only when it is not resident, and evicts the least recently called programs when there
+//! is no room for a new one. It has to be told about every upload, so all uploads to a VU should
+//! go through the same `MicroMemory`.
+//!
+//! The VIF waits for the running microprogram to end before an MPG, so a program can be evicted
+//! and overwritten by an upload in the same packet that last called it.
+
+use prussia_dma::vifcode::VifPacket;
+
+use crate::program::MicroProgram;
+
+/// The number of instructions in VU0 micro memory (4 KiB).
+pub const VU0_SIZE: usize = 512;
+
+/// The number of instructions in VU1 micro memory (16 KiB).
+pub const VU1_SIZE: usize = 2048;
+
+/// The most programs which can be resident at once.
+pub const MAX_RESIDENT: usize = 8;
+
+/// A program in micro memory.
+#[derive(Clone, Copy)]
+struct Resident {
+    program: MicroProgram,
+    start: u16,
+    last_used: u32,
+}
+
+impl Resident {
+    fn end(&self) -> usize {
+        self.start as usize + self.program.len()
+    }
+}
+
+/// The programs resident in a VU's micro memory.
+pub struct MicroMemory {
+    size: usize,
+    resident: [Option<Resident>; MAX_RESIDENT],
+    clock: u32,
+    /// The program started by the last MSCAL and the address it was started at.
+    last_call: Option<(MicroProgram, u16)>,
+}
+
+impl MicroMemory {
+    /// Track VU0 micro memory, which is assumed to hold nothing yet.
+    pub fn vu0() -> Self {
+        MicroMemory::new(VU0_SIZE)
+    }
+
+    /// Track VU1 micro memory, which is assumed to hold nothing yet.
+    pub fn vu1() -> Self {
+        MicroMemory::new(VU1_SIZE)
+    }
+
+    fn new(size: usize) -> Self {
+        MicroMemory {
+            size,
+            resident: [None; MAX_RESIDENT],
+            clock: 0,
+            last_call: None,
+        }
+    }
+
+    /// The number of instructions micro memory holds.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The instruction address `program` is resident at, if it is.
+    pub fn address(&self, program: &MicroProgram) -> Option<u16> {
+        self.find(program).map(|i| self.slot(i).start)
+    }
+
+    /// Whether `program` is resident.
+    pub fn is_resident(&self, program: &MicroProgram) -> bool {
+        self.find(program).is_some()
+    }
+
+    /// The program started by the last `call`, and the instruction address it was started at, if
+    /// it is still resident.
+    pub fn last_call(&self) -> Option<(&MicroProgram, u16)> {
+        self.last_call
+            .as_ref()
+            .map(|(program, address)| (program, *address))
+    }
+
+    /// Forget `program`, so that the space it occupies can be reused.
+    pub fn evict(&mut self, program: &MicroProgram) {
+        if let Some(i) = self.find(program) {
+            self.remove(i);
+        }
+    }
+
+    /// Forget every program, such as after micro memory has been written to directly.
+    pub fn clear(&mut self) {
+        self.resident = [None; MAX_RESIDENT];
+        self.last_call = None;
+    }
+
+    /// Make `program` resident, appending MPGs to `packet` to upload it unless it already is.
+    /// Returns the instruction address it is resident at.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `program` is larger than micro memory.
+    pub fn upload(&mut self, packet: &mut VifPacket, program: &MicroProgram) -> u16 {
+        assert!(
+            program.len() <= self.size,
+            "Microprogram {} does not fit in micro memory",
+            program.name()
+        );
+        self.clock = self.clock.wrapping_add(1);
+        if let Some(i) = self.find(program) {
+            let resident = self.resident[i].as_mut().unwrap();
+            resident.last_used = self.clock;
+            return resident.start;
+        }
+
+        let start = loop {
+            if let Some(start) = self.space_for(program.len()) {
+                break start;
+            }
+            self.evict_least_recent();
+        };
+        if self.resident.iter().all(Option::is_some) {
+            self.evict_least_recent();
+        }
+        let slot = self
+            .resident
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .unwrap();
+        *slot = Some(Resident {
+            program: *program,
+            start,
+            last_used: self.clock,
+        });
+
+        packet.mpg(start, program.code());
+        start
+    }
+
+    /// Upload `program` if needed, then append an MSCAL to `packet` starting it at `entry`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `program` has no entry point called `entry`, or is larger than micro memory.
+    pub fn call(&mut self, packet: &mut VifPacket, program: &MicroProgram, entry: &str) {
+        let offset = program.entry(entry).unwrap_or_else(|| {
+            panic!(
+                "Microprogram {} has no entry point {}",
+                program.name(),
+                entry
+            )
+        });
+        let start = self.upload(packet, program);
+        packet.mscal(start + offset);
+        self.last_call = Some((*program, start + offset));
+    }
+
+    /// Forget the program in slot `i`, and the last call if it was to that program.
+    fn remove(&mut self, i: usize) {
+        let program = self.slot(i).program;
+        if self
+            .last_call
+            .is_some_and(|(called, _)| called.same_code(&program))
+        {
+            self.last_call = None;
+        }
+        self.resident[i] = None;
+    }
+
+    fn slot(&self, i: usize) -> &Resident {
+        self.resident[i].as_ref().unwrap()
+    }
+
+    fn find(&self, program: &MicroProgram) -> Option<usize> {
+        self.resident
+            .iter()
+            .position(|slot| slot.is_some_and(|resident| resident.program.same_code(program)))
+    }
+
+    /// The lowest address with room for `len` instructions between resident programs.
+    fn space_for(&self, len: usize) -> Option<u16> {
+        let mut start = 0;
+        loop {
+            let end = start + len;
+            if end > self.size {
+                return None;
+            }
+            // Skip past the first program overlapping the candidate space, if any.
+            let overlap = self
+                .resident
+                .iter()
+                .flatten()
+                .filter(|resident| (resident.start as usize) < end && resident.end() > start)
+                .map(Resident::end)
+                .max();
+            match overlap {
+                Some(overlap_end) => start = overlap_end,
+                None => return Some(start as u16),
+            }
+        }
+    }
+
+    fn evict_least_recent(&mut self) {
+        let least_recent = self
+            .resident
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.map(|resident| (i, resident.last_used)))
+            .max_by_key(|&(_, last_used)| self.clock.wrapping_sub(last_used))
+            .map(|(i, _)| i);
+        if let Some(i) = least_recent {
+            self.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aligned::Aligned;
+    use prussia_dma::vifcode::VifCode;
+
+    static SMALL: [u64; 0x300] = [0; 0x300];
+    static LARGE: [u64; 0x500] = [0; 0x500];
+    static OTHER: [u64; 0x300] = [0; 0x300];
+
+    #[test]
+    fn upload_is_skipped_when_resident() {
+        let program = MicroProgram::new("small", &SMALL, &[("main", 0), ("clip", 8)]);
+        let mut memory = MicroMemory::vu1();
+        let mut buffer = Aligned([0u128; 0x400]);
+
+        let mut packet = VifPacket::new(&mut buffer);
+        memory.call(&mut packet, &program, "main");
+        // 0x300 instructions need three MPGs, each after a NOP to align the microcode.
+        assert_eq!(packet.len(), 3 * 2 + 0x300 * 2 + 1);
+
+        let mut packet = VifPacket::new(&mut buffer);
+        memory.call(&mut packet, &program, "clip");
+        let packet: &[u128] = packet.finish();
+        assert_eq!(packet[0] as u32, VifCode::mscal(8).into());
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let small = MicroProgram::new("small", &SMALL, &[]);
+        let large = MicroProgram::new("large", &LARGE, &[]);
+        let other = MicroProgram::new("other", &OTHER, &[]);
+        let mut memory = MicroMemory::vu1();
+        let mut buffer = Aligned([0u128; 0x800]);
+
+        assert_eq!(memory.upload(&mut VifPacket::new(&mut buffer), &small), 0);
+        assert_eq!(
+            memory.upload(&mut VifPacket::new(&mut buffer), &large),
+            0x300
+        );
+        memory.upload(&mut VifPacket::new(&mut buffer), &small);
+
+        // Only 0x100 instructions are free, so the large program makes way.
+        assert_eq!(
+            memory.upload(&mut VifPacket::new(&mut buffer), &other),
+            0x300
+        );
+        assert!(memory.is_resident(&small));
+        assert!(!memory.is_resident(&large));
+    }
+
+    #[test]
+    fn last_call_is_forgotten_on_eviction() {
+        let program = MicroProgram::new("small", &SMALL, &[("main", 0), ("clip", 8)]);
+        let mut memory = MicroMemory::vu1();
+        let mut buffer = Aligned([0u128; 0x400]);
+
+        memory.call(&mut VifPacket::new(&mut buffer), &program, "clip");
+        assert_eq!(
+            memory
+                .last_call()
+                .map(|(called, address)| (called.name(), address)),
+            Some(("small", 8))
+        );
+        memory.evict(&program);
+        assert!(memory.last_call().is_none());
+    }
+}
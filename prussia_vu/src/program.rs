@@ -0,0 +1,128 @@
+//! Microprograms for the Vector Units.
+//!
+//! A microprogram is a block of 64-bit VU instructions, each holding an upper and a lower
+//! instruction, and a list of named entry points into it. It is position-independent as long as
+//! its branches are relative, which VU branches always are, so it can be uploaded anywhere in
+//! micro memory; entry points are offsets from its start.
+
+use core::fmt;
+
+/// A microprogram and its entry points.
+#[derive(Clone, Copy)]
+pub struct MicroProgram {
+    name: &'static str,
+    code: &'static [u64],
+    entries: &'static [(&'static str, u16)],
+}
+
+impl MicroProgram {
+    /// Create a microprogram called `name` from `code`, with `entries` giving the instruction
+    /// offset of each entry point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `code` is empty, or an entry point is outside it.
+    pub fn new(
+        name: &'static str,
+        code: &'static [u64],
+        entries: &'static [(&'static str, u16)],
+    ) -> Self {
+        assert!(!code.is_empty(), "Microprogram {} is empty", name);
+        for &(entry, offset) in entries {
+            assert!(
+                (offset as usize) < code.len(),
+                "Entry point {} is outside microprogram {}",
+                entry,
+                name
+            );
+        }
+        MicroProgram {
+            name,
+            code,
+            entries,
+        }
+    }
+
+    /// The name of the microprogram.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The instructions of the microprogram.
+    pub fn code(&self) -> &'static [u64] {
+        self.code
+    }
+
+    /// The number of instructions in the microprogram.
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Whether the microprogram has no instructions, which `new` does not allow.
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// The instruction offset of the entry point called `entry`, if there is one.
+    pub fn entry(&self, entry: &str) -> Option<u16> {
+        self.entries
+            .iter()
+            .find(|&&(name, _)| name == entry)
+            .map(|&(_, offset)| offset)
+    }
+
+    /// Whether `other` has the same code as this microprogram.
+    pub fn same_code(&self, other: &MicroProgram) -> bool {
+        self.code.as_ptr() == other.code.as_ptr() && self.code.len() == other.code.len()
+    }
+}
+
+impl fmt::Debug for MicroProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MicroProgram")
+            .field("name", &self.name)
+            .field("len", &self.code.len())
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+/// Embed a microcode binary, such as one assembled with `dvp-as` and extracted with `objcopy -O
+/// binary`, as a `&'static [u64]` for `MicroProgram::new`.
+///
+/// ```ignore
+/// let code: &'static [u64] = prussia_vu::include_microcode!("transform.bin");
+/// ```
+#[macro_export]
+macro_rules! include_microcode {
+    ($path:expr) => {{
+        #[repr(C, align(8))]
+        struct Microcode<B: ?Sized>(B);
+        static MICROCODE: &Microcode<[u8]> = &Microcode(*include_bytes!($path));
+        // The bytes are aligned for u64, and any bit pattern is a valid u64.
+        unsafe {
+            core::slice::from_raw_parts(MICROCODE.0.as_ptr() as *const u64, MICROCODE.0.len() / 8)
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CODE: [u64; 4] = [0; 4];
+
+    #[test]
+    fn entry_points() {
+        let program = MicroProgram::new("test", &CODE, &[("main", 0), ("clip", 2)]);
+        assert_eq!(program.entry("clip"), Some(2));
+        assert_eq!(program.entry("cull"), None);
+        assert!(program.same_code(&MicroProgram::new("other", &CODE, &[])));
+    }
+
+    #[test]
+    #[should_panic]
+    fn entry_outside_program() {
+        MicroProgram::new("test", &CODE, &[("main", 4)]);
+    }
+}
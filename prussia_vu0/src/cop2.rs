@@ -0,0 +1,336 @@
+//! VU0 macro mode instructions, issued by the EE as COP2 instructions.
+//!
+//! The assembler does not know the VU0 mnemonics, so instructions are written as `.word`
+//! expressions. Operands are loaded into VF registers with LQC2 from fixed GPRs ($8 to $10), and
+//! results stored back with SQC2. VF0 always holds (0, 0, 0, 1).
+
+use core::arch::asm;
+
+use crate::{Mat4, Vec4};
+
+/// `LQC2 vf, offset(base)`: load a quadword into a VF register.
+macro_rules! lqc2 {
+    ($vf:literal, $offset:literal, $base:literal) => {
+        concat!(
+            ".word 0xD8000000 | (",
+            $base,
+            " << 21) | (",
+            $vf,
+            " << 16) | ",
+            $offset
+        )
+    };
+}
+
+/// `SQC2 vf, offset(base)`: store a VF register to a quadword.
+macro_rules! sqc2 {
+    ($vf:literal, $offset:literal, $base:literal) => {
+        concat!(
+            ".word 0xF8000000 | (",
+            $base,
+            " << 21) | (",
+            $vf,
+            " << 16) | ",
+            $offset
+        )
+    };
+}
+
+/// A COP2 macro instruction. `dest` selects the fields written (x = 8, y = 4, z = 2, w = 1), and
+/// `op` is the 6-bit function, or the 11-bit function of instructions without an FD operand.
+macro_rules! vu {
+    ($dest:literal, $ft:literal, $fs:literal, $fd:literal, $op:literal) => {
+        concat!(
+            ".word 0x4A000000 | (",
+            $dest,
+            " << 21) | (",
+            $ft,
+            " << 16) | (",
+            $fs,
+            " << 11) | (",
+            $fd,
+            " << 6) | ",
+            $op
+        )
+    };
+}
+
+/// VF1 and VF2 from `a` and `b`, an operation `op` from them to VF3, and VF3 to `out`.
+macro_rules! binary {
+    ($a:expr, $b:expr, $($op:expr),*) => {{
+        let mut out = Vec4::ZERO;
+        unsafe {
+            asm!(
+                lqc2!(1, 0, 8),
+                lqc2!(2, 0, 9),
+                $($op,)*
+                sqc2!(3, 0, 10),
+                in("$8") $a as *const Vec4,
+                in("$9") $b as *const Vec4,
+                in("$10") &mut out as *mut Vec4,
+                options(nostack),
+            )
+        };
+        out
+    }};
+}
+
+/// Q into the x field of VF3, once it is ready, and VF3 to `out`.
+macro_rules! q {
+    ($a:expr, $b:expr, $op:expr) => {{
+        let a = Vec4::new($a, 0.0, 0.0, 0.0);
+        let b = Vec4::new($b, 0.0, 0.0, 0.0);
+        binary!(
+            &a,
+            &b,
+            $op,
+            // VWAITQ
+            vu!(0, 0, 0, 0, 0x3BF),
+            // VADDq.x VF3, VF0, Q
+            vu!(8, 0, 0, 3, 0x20)
+        )
+        .x
+    }};
+}
+
+pub fn add(a: &Vec4, b: &Vec4) -> Vec4 {
+    // VADD.xyzw VF3, VF1, VF2
+    binary!(a, b, vu!(0xF, 2, 1, 3, 0x28))
+}
+
+pub fn sub(a: &Vec4, b: &Vec4) -> Vec4 {
+    // VSUB.xyzw VF3, VF1, VF2
+    binary!(a, b, vu!(0xF, 2, 1, 3, 0x2C))
+}
+
+pub fn mul(a: &Vec4, b: &Vec4) -> Vec4 {
+    // VMUL.xyzw VF3, VF1, VF2
+    binary!(a, b, vu!(0xF, 2, 1, 3, 0x2A))
+}
+
+pub fn mul_add(a: &Vec4, b: &Vec4, c: &Vec4) -> Vec4 {
+    let mut out = Vec4::ZERO;
+    unsafe {
+        asm!(
+            lqc2!(1, 0, 8),
+            lqc2!(2, 0, 9),
+            lqc2!(3, 0, 10),
+            // VMULAw.xyzw ACC, VF1, VF0w
+            vu!(0xF, 0, 1, 0, 0x1BF),
+            // VMADD.xyzw VF4, VF2, VF3
+            vu!(0xF, 3, 2, 4, 0x29),
+            sqc2!(4, 0, 11),
+            in("$8") a as *const Vec4,
+            in("$9") b as *const Vec4,
+            in("$10") c as *const Vec4,
+            in("$11") &mut out as *mut Vec4,
+            options(nostack),
+        )
+    };
+    out
+}
+
+pub fn max(a: &Vec4, b: &Vec4) -> Vec4 {
+    // VMAX.xyzw VF3, VF1, VF2
+    binary!(a, b, vu!(0xF, 2, 1, 3, 0x2B))
+}
+
+pub fn min(a: &Vec4, b: &Vec4) -> Vec4 {
+    // VMINI.xyzw VF3, VF1, VF2
+    binary!(a, b, vu!(0xF, 2, 1, 3, 0x2F))
+}
+
+pub fn abs(a: &Vec4) -> Vec4 {
+    // VABS.xyzw VF3, VF1
+    binary!(a, a, vu!(0xF, 3, 1, 0, 0x1FD))
+}
+
+pub fn dot(a: &Vec4, b: &Vec4) -> f32 {
+    binary!(
+        a,
+        b,
+        // VMUL.xyzw VF4, VF1, VF2
+        vu!(0xF, 2, 1, 4, 0x2A),
+        // VADDy.x VF3, VF4, VF4y
+        vu!(8, 4, 4, 3, 0x01),
+        // VADDz.x VF3, VF3, VF4z
+        vu!(8, 4, 3, 3, 0x02),
+        // VADDw.x VF3, VF3, VF4w
+        vu!(8, 4, 3, 3, 0x03)
+    )
+    .x
+}
+
+pub fn cross(a: &Vec4, b: &Vec4) -> Vec4 {
+    let mut out = binary!(
+        a,
+        b,
+        // VOPMULA.xyz ACC, VF1, VF2
+        vu!(0xE, 2, 1, 0, 0x2FE),
+        // VOPMSUB.xyz VF3, VF2, VF1
+        vu!(0xE, 1, 2, 3, 0x2E)
+    );
+    out.w = 0.0;
+    out
+}
+
+pub fn div(a: f32, b: f32) -> f32 {
+    // VDIV Q, VF1x, VF2x
+    q!(a, b, vu!(0, 2, 1, 0, 0x3BC))
+}
+
+pub fn sqrt(x: f32) -> f32 {
+    // VSQRT Q, VF2x
+    q!(0.0, x, vu!(0, 2, 0, 0, 0x3BD))
+}
+
+pub fn rsqrt(a: f32, b: f32) -> f32 {
+    // VRSQRT Q, VF1x, VF2x
+    q!(a, b, vu!(0, 2, 1, 0, 0x3BE))
+}
+
+pub fn transform(m: &Mat4, v: &Vec4) -> Vec4 {
+    let mut out = Vec4::ZERO;
+    unsafe {
+        asm!(
+            lqc2!(1, 0, 8),
+            lqc2!(2, 16, 8),
+            lqc2!(3, 32, 8),
+            lqc2!(4, 48, 8),
+            lqc2!(5, 0, 9),
+            // VMULAx.xyzw ACC, VF1, VF5x
+            vu!(0xF, 5, 1, 0, 0x1BC),
+            // VMADDAy.xyzw ACC, VF2, VF5y
+            vu!(0xF, 5, 2, 0, 0x0BD),
+            // VMADDAz.xyzw ACC, VF3, VF5z
+            vu!(0xF, 5, 3, 0, 0x0BE),
+            // VMADDw.xyzw VF6, VF4, VF5w
+            vu!(0xF, 5, 4, 6, 0x0B),
+            sqc2!(6, 0, 10),
+            in("$8") m as *const Mat4,
+            in("$9") v as *const Vec4,
+            in("$10") &mut out as *mut Vec4,
+            options(nostack),
+        )
+    };
+    out
+}
+
+pub fn mat_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = Mat4::IDENTITY;
+    // Each column of the result is `a` applied to a column of `b`, with `a` kept in VF1 to VF4.
+    macro_rules! column {
+        ($offset:literal) => {
+            concat!(
+                lqc2!(5, $offset, 9),
+                "\n",
+                vu!(0xF, 5, 1, 0, 0x1BC),
+                "\n",
+                vu!(0xF, 5, 2, 0, 0x0BD),
+                "\n",
+                vu!(0xF, 5, 3, 0, 0x0BE),
+                "\n",
+                vu!(0xF, 5, 4, 6, 0x0B),
+                "\n",
+                sqc2!(6, $offset, 10)
+            )
+        };
+    }
+    unsafe {
+        asm!(
+            lqc2!(1, 0, 8),
+            lqc2!(2, 16, 8),
+            lqc2!(3, 32, 8),
+            lqc2!(4, 48, 8),
+            column!(0),
+            column!(16),
+            column!(32),
+            column!(48),
+            in("$8") a as *const Mat4,
+            in("$9") b as *const Mat4,
+            in("$10") &mut out as *mut Mat4,
+            options(nostack),
+        )
+    };
+    out
+}
+
+/// `CTC2.I rt, vi`: write a GPR to a VU0 integer or control register, once VU0 is idle.
+macro_rules! ctc2 {
+    ($rt:literal, $vi:literal) => {
+        concat!(".word 0x48C00001 | (", $rt, " << 16) | (", $vi, " << 11)")
+    };
+}
+
+/// `CFC2 rt, vi`: read a VU0 integer or control register into a GPR. `interlock` is 1 for
+/// `CFC2.I`, which waits for VU0 to be idle.
+macro_rules! cfc2 {
+    ($rt:literal, $vi:literal, $interlock:literal) => {
+        concat!(
+            ".word 0x48400000 | (",
+            $rt,
+            " << 16) | (",
+            $vi,
+            " << 11) | ",
+            $interlock
+        )
+    };
+}
+
+/// The VU0 control register holding the address VCALLMSR starts at.
+const CMSAR0: u8 = 27;
+
+/// The running bit of VU0 in the VPU-STAT control register.
+const VBS0: u32 = 1 << 0;
+
+/// Run `$op` with the operand register numbered `$vi` as a literal, for the integer registers VI1
+/// to VI15 and the control register CMSAR0.
+macro_rules! with_register {
+    ($vi:expr, $op:ident) => {
+        with_register!(@match $vi, $op, 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 27)
+    };
+    (@match $vi:expr, $op:ident, $($n:literal)*) => {
+        match $vi {
+            $($n => $op!($n),)*
+            vi => panic!("VI{} cannot be written or read by the EE", vi),
+        }
+    };
+}
+
+pub fn set_integer(vi: u8, value: u16) {
+    macro_rules! write {
+        ($n:literal) => {
+            unsafe { asm!(ctc2!(8, $n), in("$8") value as u32, options(nostack)) }
+        };
+    }
+    with_register!(vi, write)
+}
+
+pub fn integer(vi: u8) -> u16 {
+    let value: u32;
+    macro_rules! read {
+        ($n:literal) => {
+            unsafe { asm!(cfc2!(8, $n, 1), out("$8") value, options(nostack)) }
+        };
+    }
+    with_register!(vi, read);
+    value as u16
+}
+
+pub fn call_micro(address: u16) {
+    set_integer(CMSAR0, address);
+    // VCALLMSR vi27
+    unsafe { asm!(vu!(0, 0, 27, 0, 0x39), options(nostack)) };
+}
+
+pub fn wait_micro() {
+    // CFC2.I waits for the microprogram to end; the register read is thrown away.
+    unsafe { asm!(cfc2!(0, 0, 1), options(nostack)) };
+}
+
+pub fn micro_running() -> bool {
+    let status: u32;
+    // CFC2 t0, vi29 (VPU-STAT), without waiting.
+    unsafe { asm!(cfc2!(8, 29, 0), out("$8") status, options(nostack)) };
+    status & VBS0 != 0
+}
@@ -0,0 +1,218 @@
+//! A portable model of VU0 arithmetic, used where there is no VU0.
+//!
+//! VU0 floats are not IEEE 754: there are no infinities or NaNs, an exponent of 255 is just a
+//! larger exponent, denormals are read as and flushed to zero, and overflow saturates at the
+//! largest magnitude. Results are rounded toward zero. Each operation here is worked out exactly
+//! in `f64` and then truncated to a VU0 float, which matches VU0 apart from the last bit of some
+//! additions of numbers with very different exponents, where VU0 has fewer guard bits.
+
+use crate::{Mat4, Vec4};
+
+/// The largest VU0 float magnitude, which is a NaN to IEEE 754.
+const MAX_BITS: u32 = 0x7FFF_FFFF;
+
+/// The value of a VU0 float.
+fn widen(x: f32) -> f64 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 31) as u64) << 63;
+    let exponent = (bits >> 23) & 0xFF;
+    if exponent == 0 {
+        return f64::from_bits(sign);
+    }
+    let exponent = (exponent as u64 + 1023 - 127) << 52;
+    let mantissa = ((bits & 0x7F_FFFF) as u64) << 29;
+    f64::from_bits(sign | exponent | mantissa)
+}
+
+/// Truncate `x` to a VU0 float, flushing to zero and saturating as VU0 does.
+fn narrow(x: f64) -> f32 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 63) as u32) << 31;
+    let exponent = ((bits >> 52) & 0x7FF) as i64 - 1023 + 127;
+    if x == 0.0 || exponent <= 0 {
+        return f32::from_bits(sign);
+    }
+    if exponent > 0xFF {
+        return f32::from_bits(sign | MAX_BITS);
+    }
+    let mantissa = ((bits >> 29) & 0x7F_FFFF) as u32;
+    f32::from_bits(sign | (exponent as u32) << 23 | mantissa)
+}
+
+/// The next VU0 float toward zero from a nonzero `x`.
+fn toward_zero(x: f32) -> f32 {
+    f32::from_bits(x.to_bits() - 1)
+}
+
+/// 2 to the power of `exponent`, which must be in the normal range of `f64`.
+fn power_of_two(exponent: i64) -> f64 {
+    f64::from_bits(((exponent + 1023) as u64) << 52)
+}
+
+pub fn add_scalar(a: f32, b: f32) -> f32 {
+    let (a, b) = (widen(a), widen(b));
+    let sum = a + b;
+    // The rounding error of the sum, which only matters if the sum is exactly a VU0 float.
+    let b_part = sum - a;
+    let error = (a - (sum - b_part)) + (b - b_part);
+    let result = narrow(sum);
+    if result != 0.0 && widen(result) == sum && error != 0.0 && (error < 0.0) != (sum < 0.0) {
+        toward_zero(result)
+    } else {
+        result
+    }
+}
+
+pub fn sub_scalar(a: f32, b: f32) -> f32 {
+    add_scalar(a, -b)
+}
+
+pub fn mul_scalar(a: f32, b: f32) -> f32 {
+    // Both have 24-bit mantissas, so the product is exact.
+    narrow(widen(a) * widen(b))
+}
+
+pub fn div(a: f32, b: f32) -> f32 {
+    let (a, b) = (widen(a), widen(b));
+    let sign = (a.is_sign_negative() != b.is_sign_negative()) as u32;
+    if b == 0.0 {
+        return f32::from_bits(sign << 31 | MAX_BITS);
+    }
+    let quotient = narrow(a / b);
+    // The quotient may have been rounded up to a VU0 float in f64; the product is exact.
+    if quotient != 0.0 && (widen(quotient) * b).abs() > a.abs() {
+        toward_zero(quotient)
+    } else {
+        quotient
+    }
+}
+
+/// The square root of the magnitude of `x`, rounded down to 51 bits.
+fn root(x: f32) -> f64 {
+    let bits = x.to_bits() & 0x7FFF_FFFF;
+    let exponent = (bits >> 23) as i64;
+    if exponent == 0 {
+        return 0.0;
+    }
+    // x = mantissa * 2^(exponent - 150), with an even power of two.
+    let mut mantissa = (0x80_0000 | bits & 0x7F_FFFF) as u128;
+    let mut power = exponent - 150;
+    if power % 2 != 0 {
+        mantissa <<= 1;
+        power -= 1;
+    }
+    let root = (mantissa << 78).isqrt();
+    root as f64 * power_of_two(power / 2 - 39)
+}
+
+pub fn sqrt(x: f32) -> f32 {
+    // VSQRT takes the square root of the magnitude, and the root is already rounded down.
+    narrow(root(x))
+}
+
+pub fn rsqrt(a: f32, b: f32) -> f32 {
+    let root = root(b);
+    if root == 0.0 {
+        return div(a, 0.0);
+    }
+    narrow(widen(a) / root)
+}
+
+fn map(a: &Vec4, b: &Vec4, f: fn(f32, f32) -> f32) -> Vec4 {
+    Vec4::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z), f(a.w, b.w))
+}
+
+pub fn add(a: &Vec4, b: &Vec4) -> Vec4 {
+    map(a, b, add_scalar)
+}
+
+pub fn sub(a: &Vec4, b: &Vec4) -> Vec4 {
+    map(a, b, sub_scalar)
+}
+
+pub fn mul(a: &Vec4, b: &Vec4) -> Vec4 {
+    map(a, b, mul_scalar)
+}
+
+pub fn mul_add(a: &Vec4, b: &Vec4, c: &Vec4) -> Vec4 {
+    // The accumulator adds the truncated product.
+    add(a, &mul(b, c))
+}
+
+pub fn max(a: &Vec4, b: &Vec4) -> Vec4 {
+    map(a, b, |a, b| if widen(a) >= widen(b) { a } else { b })
+}
+
+pub fn min(a: &Vec4, b: &Vec4) -> Vec4 {
+    map(a, b, |a, b| if widen(a) <= widen(b) { a } else { b })
+}
+
+pub fn abs(a: &Vec4) -> Vec4 {
+    let abs = |x: f32| f32::from_bits(x.to_bits() & 0x7FFF_FFFF);
+    Vec4::new(abs(a.x), abs(a.y), abs(a.z), abs(a.w))
+}
+
+pub fn dot(a: &Vec4, b: &Vec4) -> f32 {
+    let product = mul(a, b);
+    add_scalar(
+        add_scalar(add_scalar(product.x, product.y), product.z),
+        product.w,
+    )
+}
+
+pub fn cross(a: &Vec4, b: &Vec4) -> Vec4 {
+    let cross = |a1, b2, a2, b1| sub_scalar(mul_scalar(a1, b2), mul_scalar(b1, a2));
+    Vec4::new(
+        cross(a.y, b.z, a.z, b.y),
+        cross(a.z, b.x, a.x, b.z),
+        cross(a.x, b.y, a.y, b.x),
+        0.0,
+    )
+}
+
+pub fn transform(m: &Mat4, v: &Vec4) -> Vec4 {
+    let [c0, c1, c2, c3] = &m.columns;
+    let acc = mul(c0, &Vec4::splat(v.x));
+    let acc = mul_add(&acc, c1, &Vec4::splat(v.y));
+    let acc = mul_add(&acc, c2, &Vec4::splat(v.z));
+    mul_add(&acc, c3, &Vec4::splat(v.w))
+}
+
+pub fn mat_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let [c0, c1, c2, c3] = &b.columns;
+    Mat4::new([
+        transform(a, c0),
+        transform(a, c1),
+        transform(a, c2),
+        transform(a, c3),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_toward_zero() {
+        // 1/3 rounds up to 0x3EAAAAAB in IEEE 754.
+        assert_eq!(div(1.0, 3.0).to_bits(), 0x3EAA_AAAA);
+        assert_eq!(div(-1.0, 3.0).to_bits(), 0xBEAA_AAAA);
+        // sqrt(2) is between 0x3FB504F3 and 0x3FB504F4.
+        assert_eq!(sqrt(2.0).to_bits(), 0x3FB5_04F3);
+        assert_eq!(sqrt(-16.0), 4.0);
+        // 1 - 2^-30 is just below 1.
+        assert_eq!(
+            add_scalar(1.0, -f32::from_bits(0x3080_0000)).to_bits(),
+            0x3F7F_FFFF
+        );
+    }
+
+    #[test]
+    fn saturates_and_flushes() {
+        assert_eq!(mul_scalar(f32::MAX, 4.0).to_bits(), 0x7FFF_FFFF);
+        assert_eq!(div(-1.0, 0.0).to_bits(), 0xFFFF_FFFF);
+        assert_eq!(mul_scalar(f32::MIN_POSITIVE, 0.5), 0.0);
+        // An IEEE 754 infinity is just a large number.
+        assert_eq!(mul_scalar(f32::INFINITY, 0.5).to_bits(), 0x7F00_0000);
+    }
+}
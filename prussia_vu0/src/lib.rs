@@ -0,0 +1,73 @@
+//! Vector math on the PlayStation 2's Vector Unit 0.
+//!
+//! In macro mode, the EE issues VU0 instructions directly as COP2 instructions, which makes VU0
+//! the EE's vector math unit. `Vec4` and `Mat4` are quadword-aligned so that they can be loaded
+//! into VU0 registers with LQC2 and stored back with SQC2, and their operations map onto VADD,
+//! VMUL, VMADD, VOPMSUB, VDIV, VSQRT and friends. `enable` must be called before using them on
+//! the PS2.
+//!
+//! On other targets, the same operations are carried out in software following VU0's rules for
+//! floats, which round toward zero and saturate rather than overflowing to infinity, so code
+//! using this crate gets the same results when it is tested on the host.
+//!
+//! On the PS2, VU0 can also run microprograms of its own; see the `micro` module.
+//!
+//! # Examples
+//!
+//! ```
+//! use prussia_vu0::{Mat4, Vec4};
+//!
+//! prussia_vu0::enable();
+//! let model = Mat4::translation(Vec4::new(0.0, 0.0, -5.0, 0.0));
+//! let vertex = model * Vec4::new(1.0, 1.0, 1.0, 1.0);
+//! assert_eq!(vertex, Vec4::new(1.0, 1.0, -4.0, 1.0));
+//! ```
+
+#![no_std]
+#![deny(missing_docs)]
+#![cfg_attr(target_arch = "mips", feature(asm_experimental_arch))]
+
+#[cfg(target_arch = "mips")]
+mod cop2;
+#[cfg(not(target_arch = "mips"))]
+mod fallback;
+pub mod mat4;
+#[cfg(target_arch = "mips")]
+pub mod micro;
+pub mod vec4;
+
+#[cfg(target_arch = "mips")]
+use cop2 as backend;
+#[cfg(not(target_arch = "mips"))]
+use fallback as backend;
+
+pub use crate::mat4::Mat4;
+pub use crate::vec4::Vec4;
+
+/// Make COP2 usable by setting `cop0::Status::CU2`, so that macro mode instructions do not raise
+/// a coprocessor unusable exception. This does nothing on other targets.
+pub fn enable() {
+    #[cfg(target_arch = "mips")]
+    {
+        use prussia_rt::cop0::Status;
+
+        let mut status = Status::load();
+        status.insert(Status::CU2);
+        status.store();
+    }
+}
+
+/// `a / b` (VDIV). Division by zero saturates.
+pub fn div(a: f32, b: f32) -> f32 {
+    backend::div(a, b)
+}
+
+/// The square root of the magnitude of `x` (VSQRT).
+pub fn sqrt(x: f32) -> f32 {
+    backend::sqrt(x)
+}
+
+/// `a` divided by the square root of the magnitude of `b` (VRSQRT). Division by zero saturates.
+pub fn rsqrt(a: f32, b: f32) -> f32 {
+    backend::rsqrt(a, b)
+}
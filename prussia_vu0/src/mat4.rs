@@ -0,0 +1,96 @@
+//! 4x4 matrices.
+
+use core::ops::Mul;
+
+use crate::backend;
+use crate::Vec4;
+
+/// A 4x4 matrix, stored as four column vectors so that transforming a vector is a sum of columns
+/// (VMULA/VMADDA/VMADD).
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mat4 {
+    /// The columns of the matrix.
+    pub columns: [Vec4; 4],
+}
+
+impl Mat4 {
+    /// The identity matrix.
+    pub const IDENTITY: Mat4 = Mat4::new([
+        Vec4::new(1.0, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 1.0, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(0.0, 0.0, 0.0, 1.0),
+    ]);
+
+    /// Create a matrix from its columns.
+    pub const fn new(columns: [Vec4; 4]) -> Self {
+        Mat4 { columns }
+    }
+
+    /// A matrix translating by the first three elements of `offset`.
+    pub const fn translation(offset: Vec4) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.columns[3] = Vec4::new(offset.x, offset.y, offset.z, 1.0);
+        matrix
+    }
+
+    /// A matrix scaling each axis by the corresponding element of `scale`.
+    pub const fn scale(scale: Vec4) -> Self {
+        Mat4::new([
+            Vec4::new(scale.x, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, scale.y, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, scale.z, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, scale.w),
+        ])
+    }
+
+    /// The matrix with its rows and columns swapped.
+    pub fn transpose(&self) -> Mat4 {
+        let [a, b, c, d] = self.columns;
+        Mat4::new([
+            Vec4::new(a.x, b.x, c.x, d.x),
+            Vec4::new(a.y, b.y, c.y, d.y),
+            Vec4::new(a.z, b.z, c.z, d.z),
+            Vec4::new(a.w, b.w, c.w, d.w),
+        ])
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    /// Matrix multiplication, applying `rhs` first.
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        backend::mat_mul(&self, &rhs)
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    /// Transform `rhs` by the matrix.
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        backend::transform(&self, &rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms() {
+        let point = Vec4::new(1.0, 2.0, 3.0, 1.0);
+        let translate = Mat4::translation(Vec4::new(10.0, 20.0, 30.0, 0.0));
+        let scale = Mat4::scale(Vec4::new(2.0, 2.0, 2.0, 1.0));
+
+        assert_eq!(Mat4::IDENTITY * point, point);
+        assert_eq!(translate * point, Vec4::new(11.0, 22.0, 33.0, 1.0));
+        assert_eq!((translate * scale) * point, translate * (scale * point));
+        assert_eq!(
+            (translate * scale).transpose().columns[3],
+            Vec4::new(0.0, 0.0, 0.0, 1.0)
+        );
+    }
+}
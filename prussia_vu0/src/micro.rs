@@ -0,0 +1,214 @@
+//! VU0 micro mode.
+//!
+//! Besides executing COP2 instructions for the EE, VU0 can run microprograms from its 4 KiB of
+//! micro memory. `MicroMode` uploads programs through VIF0, keeping track of which are resident
+//! with a `prussia_vu::memory::MicroMemory`, and starts them with VCALLMSR. Arguments and results
+//! are handed over in the integer registers VI1 to VI15 with CTC2 and CFC2, and larger data is
+//! copied to and from VU0 data memory, which the EE sees at `DATA_MEMORY`.
+//!
+//! The interlocked forms of CTC2 and CFC2 stall the EE until VU0 is idle, so nothing here writes
+//! to VU0 while a microprogram is running, and waiting for a microprogram to end does not spin.
+//! Macro mode instructions also wait for VU0 to be idle, so the rest of this crate can be used
+//! freely, but they share the VF registers with microprograms.
+//!
+//! Uploads end with a VIFcode raising the VIF0 interrupt, and the uploading thread sleeps until
+//! `upload_handler` sees it, so `upload_handler` must be registered before uploading.
+
+use core::ptr;
+
+use aligned::{Aligned, A16};
+use prussia_bios::Event;
+use prussia_dma::vifcode::{VifCode, VifPacket};
+use prussia_dma::{Transfer, Vif0};
+use prussia_vu::memory::MicroMemory;
+use prussia_vu::program::MicroProgram;
+
+use crate::backend;
+
+/// The address of VU0 data memory as seen by the EE.
+pub const DATA_MEMORY: usize = 0x1100_4000;
+
+/// The number of quadwords in VU0 data memory (4 KiB).
+pub const DATA_SIZE: usize = 256;
+
+/// VIF0_FBRST, which resets VIF0 or cancels its stalls.
+const VIF0_FBRST: *mut u32 = 0x1000_3810 as *mut u32;
+
+/// The FBRST bit cancelling the stall after an interrupting VIFcode.
+const FBRST_STC: u32 = 1 << 3;
+
+/// The VIF0 interrupts seen by `upload_handler`.
+static UPLOADS: Event<1> = Event::new();
+
+/// An INTC handler for the VIF0 interrupt raised at the end of each upload, which lets VIF0
+/// continue and wakes the thread waiting in `MicroMode::upload`.
+///
+/// Register this for the VIF0 cause with `prussia_bios::add_intc_handler` and enable it with
+/// `prussia_bios::enable_intc`.
+pub fn upload_handler(_cause: i32) -> i32 {
+    // VIF0 stalls after an interrupting VIFcode until the stall is cancelled.
+    unsafe { ptr::write_volatile(VIF0_FBRST, FBRST_STC) };
+    UPLOADS.isignal();
+    0
+}
+
+/// VU0 running microprograms.
+pub struct MicroMode {
+    memory: MicroMemory,
+}
+
+impl MicroMode {
+    /// Take over VU0 micro memory, which is assumed to hold nothing yet.
+    pub fn new() -> Self {
+        MicroMode {
+            memory: MicroMemory::vu0(),
+        }
+    }
+
+    /// The programs resident in micro memory.
+    pub fn memory(&self) -> &MicroMemory {
+        &self.memory
+    }
+
+    /// Make `program` resident, uploading it through `vif0` with `buffer` as the packet unless it
+    /// already is, and wait for the upload to complete. Returns the instruction address it is
+    /// resident at.
+    ///
+    /// The current thread sleeps until `upload_handler` sees VIF0 finish the upload.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `program` is larger than micro memory, or `buffer` is too small for the upload.
+    pub fn upload(
+        &mut self,
+        vif0: Vif0,
+        buffer: &mut Aligned<A16, [u128]>,
+        program: &MicroProgram,
+    ) -> (Vif0, u16) {
+        let mut packet = VifPacket::new(buffer);
+        let start = self.memory.upload(&mut packet, program);
+        if packet.is_empty() {
+            return (vif0, start);
+        }
+
+        // The transfer ends once VIF0 has been sent the packet, not once it has written the
+        // microcode, so VIF0 raises an interrupt after the last MPG instead.
+        packet.code(VifCode::nop().with_irq(true));
+        let uploads = UPLOADS.count();
+        let (vif0, ()) = Transfer::from_mem_scoped(vif0, packet.finish(), |_| ());
+        UPLOADS.sleep_until(|| UPLOADS.count() != uploads);
+        (vif0, start)
+    }
+
+    /// Upload `program` if needed as with `upload`, then start it at `entry` with each of
+    /// `integers` written to its VI register first.
+    ///
+    /// If a microprogram is already running, this waits for it to end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `program` has no entry point called `entry`, is larger than micro memory or
+    /// `buffer` is too small for the upload, or if a register is not one of VI1 to VI15.
+    pub fn call(
+        &mut self,
+        vif0: Vif0,
+        buffer: &mut Aligned<A16, [u128]>,
+        program: &MicroProgram,
+        entry: &str,
+        integers: &[(u8, u16)],
+    ) -> Vif0 {
+        let offset = program.entry(entry).unwrap_or_else(|| {
+            panic!(
+                "Microprogram {} has no entry point {}",
+                program.name(),
+                entry
+            )
+        });
+        let (vif0, start) = self.upload(vif0, buffer, program);
+        for &(vi, value) in integers {
+            self.set_integer(vi, value);
+        }
+        backend::call_micro(start + offset);
+        vif0
+    }
+
+    /// Whether a microprogram is running, read from VPU-STAT.
+    pub fn is_running(&self) -> bool {
+        backend::micro_running()
+    }
+
+    /// Wait for the running microprogram, if any, to end.
+    pub fn wait(&self) {
+        backend::wait_micro();
+    }
+
+    /// Write `value` to the integer register `vi`, after waiting for VU0 to be idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vi` is not one of 1 to 15.
+    pub fn set_integer(&mut self, vi: u8, value: u16) {
+        assert!(
+            (1..=15).contains(&vi),
+            "VI{} is not an integer register",
+            vi
+        );
+        backend::set_integer(vi, value);
+    }
+
+    /// Read the integer register `vi`, after waiting for VU0 to be idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vi` is not one of 1 to 15.
+    pub fn integer(&self, vi: u8) -> u16 {
+        assert!(
+            (1..=15).contains(&vi),
+            "VI{} is not an integer register",
+            vi
+        );
+        backend::integer(vi)
+    }
+
+    /// Copy `data` to data memory at quadword address `addr`, after waiting for VU0 to be idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not fit in data memory at `addr`.
+    pub fn write_data(&mut self, addr: usize, data: &[u128]) {
+        let memory = self.data(addr, data.len());
+        self.wait();
+        for (i, &quadword) in data.iter().enumerate() {
+            unsafe { ptr::write_volatile(memory.add(i), quadword) };
+        }
+    }
+
+    /// Copy data memory at quadword address `addr` into `data`, after waiting for VU0 to be idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not fit in data memory at `addr`.
+    pub fn read_data(&self, addr: usize, data: &mut [u128]) {
+        let memory = self.data(addr, data.len());
+        self.wait();
+        for (i, quadword) in data.iter_mut().enumerate() {
+            *quadword = unsafe { ptr::read_volatile(memory.add(i)) };
+        }
+    }
+
+    fn data(&self, addr: usize, len: usize) -> *mut u128 {
+        assert!(
+            addr + len <= DATA_SIZE,
+            "{} quadwords at {:#x} do not fit in VU0 data memory",
+            len,
+            addr
+        );
+        (DATA_MEMORY as *mut u128).wrapping_add(addr)
+    }
+}
+
+impl Default for MicroMode {
+    fn default() -> Self {
+        MicroMode::new()
+    }
+}
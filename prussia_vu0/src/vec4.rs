@@ -0,0 +1,152 @@
+//! Four-element vectors.
+
+use core::ops::{Add, Mul, Neg, Sub};
+
+use crate::backend;
+
+/// A vector of four floats, laid out as a quadword so that it can be loaded straight into a VU0
+/// register.
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec4 {
+    /// The first element.
+    pub x: f32,
+    /// The second element.
+    pub y: f32,
+    /// The third element.
+    pub z: f32,
+    /// The fourth element.
+    pub w: f32,
+}
+
+impl Vec4 {
+    /// The zero vector.
+    pub const ZERO: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.0);
+
+    /// Create a vector from its elements.
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Vec4 { x, y, z, w }
+    }
+
+    /// Create a vector with every element set to `value`.
+    pub const fn splat(value: f32) -> Self {
+        Vec4::new(value, value, value, value)
+    }
+
+    /// `self + a * b`, element by element (VMULA/VMADD).
+    pub fn mul_add(self, a: Vec4, b: Vec4) -> Vec4 {
+        backend::mul_add(&self, &a, &b)
+    }
+
+    /// The larger of each pair of elements (VMAX).
+    pub fn max(self, other: Vec4) -> Vec4 {
+        backend::max(&self, &other)
+    }
+
+    /// The smaller of each pair of elements (VMINI).
+    pub fn min(self, other: Vec4) -> Vec4 {
+        backend::min(&self, &other)
+    }
+
+    /// The magnitude of each element (VABS).
+    pub fn abs(self) -> Vec4 {
+        backend::abs(&self)
+    }
+
+    /// The dot product of all four elements.
+    pub fn dot(self, other: Vec4) -> f32 {
+        backend::dot(&self, &other)
+    }
+
+    /// The cross product of the first three elements (VOPMULA/VOPMSUB), with `w` set to zero.
+    pub fn cross(self, other: Vec4) -> Vec4 {
+        backend::cross(&self, &other)
+    }
+
+    /// The length of the vector, over all four elements.
+    pub fn length(self) -> f32 {
+        crate::sqrt(self.dot(self))
+    }
+
+    /// The vector scaled to a length of one (VRSQRT). A zero vector saturates.
+    pub fn normalize(self) -> Vec4 {
+        self * crate::rsqrt(1.0, self.dot(self))
+    }
+}
+
+impl Add for Vec4 {
+    type Output = Vec4;
+
+    /// Element-by-element addition (VADD).
+    fn add(self, rhs: Vec4) -> Vec4 {
+        backend::add(&self, &rhs)
+    }
+}
+
+impl Sub for Vec4 {
+    type Output = Vec4;
+
+    /// Element-by-element subtraction (VSUB).
+    fn sub(self, rhs: Vec4) -> Vec4 {
+        backend::sub(&self, &rhs)
+    }
+}
+
+impl Mul for Vec4 {
+    type Output = Vec4;
+
+    /// Element-by-element multiplication (VMUL).
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        backend::mul(&self, &rhs)
+    }
+}
+
+impl Mul<f32> for Vec4 {
+    type Output = Vec4;
+
+    /// Multiplication of every element by `rhs` (VMUL).
+    fn mul(self, rhs: f32) -> Vec4 {
+        backend::mul(&self, &Vec4::splat(rhs))
+    }
+}
+
+impl Neg for Vec4 {
+    type Output = Vec4;
+
+    fn neg(self) -> Vec4 {
+        // Negation only flips sign bits, which needs no rounding.
+        Vec4::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        let b = Vec4::new(0.5, -1.0, 2.0, 0.0);
+
+        assert_eq!(a + b, Vec4::new(1.5, 1.0, 5.0, 4.0));
+        assert_eq!(a - b, Vec4::new(0.5, 3.0, 1.0, 4.0));
+        assert_eq!(a * 2.0, Vec4::new(2.0, 4.0, 6.0, 8.0));
+        assert_eq!(a.mul_add(b, b), Vec4::new(1.25, 3.0, 7.0, 4.0));
+        assert_eq!(b.max(-a).abs(), Vec4::new(0.5, 1.0, 2.0, 0.0));
+        assert_eq!(a.dot(b), 4.5);
+    }
+
+    #[test]
+    fn cross_and_normalize() {
+        let x = Vec4::new(1.0, 0.0, 0.0, 7.0);
+        let y = Vec4::new(0.0, 1.0, 0.0, 7.0);
+        assert_eq!(x.cross(y), Vec4::new(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(y.cross(x), Vec4::new(0.0, 0.0, -1.0, 0.0));
+
+        assert_eq!(Vec4::new(3.0, 0.0, 4.0, 0.0).length(), 5.0);
+        assert_eq!(
+            Vec4::new(0.0, 0.0, -4.0, 0.0).normalize(),
+            Vec4::new(0.0, 0.0, -1.0, 0.0)
+        );
+    }
+}